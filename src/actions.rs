@@ -0,0 +1,294 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::init::ConfigFile;
+use crate::quarantine::quarantine;
+use crate::reflink::reflink;
+
+/// What to do with redundant copy of file
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Action {
+    Delete,
+    MoveTo(PathBuf),
+    HardLink,
+    SymLink { relative: bool },
+    Reflink,
+}
+
+/// Result of successfully applied action
+#[derive(Clone, Debug, Default)]
+pub struct Applied {
+    pub reclaimed: u64,
+    pub moved_to: Option<PathBuf>,
+}
+
+impl Applied {
+    fn reclaimed(reclaimed: u64) -> Self {
+        Self {
+            reclaimed,
+            moved_to: None,
+        }
+    }
+}
+
+impl Action {
+    pub fn from_config(cfg: &ConfigFile) -> Self {
+        if cfg.hardlink {
+            Action::HardLink
+        } else if cfg.reflink {
+            Action::Reflink
+        } else if let Some(dir) = &cfg.move_to {
+            Action::MoveTo(dir.clone())
+        } else if cfg.symlink {
+            Action::SymLink {
+                relative: cfg.relative_symlinks,
+            }
+        } else {
+            Action::Delete
+        }
+    }
+
+    /// Action replaces redundant copy with a link to kept file
+    pub fn needs_target(&self) -> bool {
+        !matches!(self, Action::Delete | Action::MoveTo(_))
+    }
+
+    pub fn apply(&self, victim: &str, kept: Option<&str>) -> io::Result<Applied> {
+        let reclaimed = match self {
+            Action::Delete => {
+                let size = freed_size(&fs::symlink_metadata(victim)?);
+                fs::remove_file(victim)?;
+                size
+            }
+            Action::MoveTo(dir) => {
+                let (size, moved_to) = quarantine(Path::new(victim), kept.map(Path::new), dir)?;
+                return Ok(Applied {
+                    reclaimed: size,
+                    moved_to: Some(moved_to),
+                });
+            }
+            Action::HardLink => hard_link(Path::new(victim), Path::new(target(kept)?))?,
+            Action::SymLink { relative } => {
+                sym_link(Path::new(victim), Path::new(target(kept)?), *relative)?
+            }
+            Action::Reflink => reflink(Path::new(victim), Path::new(target(kept)?))?,
+        };
+
+        Ok(Applied::reclaimed(reclaimed))
+    }
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Action::Delete => write!(f, "delete"),
+            Action::MoveTo(_) => write!(f, "move"),
+            Action::HardLink => write!(f, "hardlink"),
+            Action::SymLink { .. } => write!(f, "symlink"),
+            Action::Reflink => write!(f, "reflink"),
+        }
+    }
+}
+
+fn target(kept: Option<&str>) -> io::Result<&str> {
+    kept.ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no file to keep in group"))
+}
+
+fn temp_path(victim: &Path) -> io::Result<PathBuf> {
+    let file_name = victim
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "path has no file name"))?;
+
+    let mut temp_name = std::ffi::OsString::from(".");
+    temp_name.push(file_name);
+    temp_name.push(".fundoubler-tmp");
+
+    Ok(victim.with_file_name(temp_name))
+}
+
+/// Puts new file at temp path next to victim, then atomically renames it over victim
+pub fn replace_with(victim: &Path, create: impl FnOnce(&Path) -> io::Result<()>) -> io::Result<()> {
+    let temp = temp_path(victim)?;
+    create(&temp)?;
+
+    if let Err(e) = fs::rename(&temp, victim) {
+        let _ = fs::remove_file(&temp);
+        return Err(e);
+    }
+
+    Ok(())
+}
+
+#[cfg(unix)]
+fn same_file_system(victim: &Path, kept: &Path) -> io::Result<(bool, bool)> {
+    use std::os::unix::fs::MetadataExt;
+
+    let victim_meta = fs::metadata(victim)?;
+    let kept_meta = fs::metadata(kept)?;
+
+    Ok((
+        victim_meta.dev() == kept_meta.dev(),
+        victim_meta.dev() == kept_meta.dev() && victim_meta.ino() == kept_meta.ino(),
+    ))
+}
+
+#[cfg(not(unix))]
+fn same_file_system(_victim: &Path, _kept: &Path) -> io::Result<(bool, bool)> {
+    Ok((true, false))
+}
+
+// Data of a file with other hard links stays on disk after unlinking it
+#[cfg(unix)]
+fn freed_size(metadata: &fs::Metadata) -> u64 {
+    use std::os::unix::fs::MetadataExt;
+
+    if metadata.nlink() > 1 {
+        0
+    } else {
+        metadata.len()
+    }
+}
+
+#[cfg(not(unix))]
+fn freed_size(metadata: &fs::Metadata) -> u64 {
+    metadata.len()
+}
+
+fn hard_link(victim: &Path, kept: &Path) -> io::Result<u64> {
+    let (same_fs, same_file) = same_file_system(victim, kept)?;
+
+    if same_file {
+        return Ok(0);
+    }
+
+    if !same_fs {
+        return Err(io::Error::other(format!(
+            "{} is on another file system",
+            kept.display()
+        )));
+    }
+
+    let size = freed_size(&fs::metadata(victim)?);
+    replace_with(victim, |temp| fs::hard_link(kept, temp))?;
+    Ok(size)
+}
+
+/// Canonical path of file, the file itself may be a symlink
+pub fn absolute_path(path: &Path) -> io::Result<PathBuf> {
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "path has no file name"))?;
+    let parent = match path.parent() {
+        Some(p) if !p.as_os_str().is_empty() => p,
+        _ => Path::new("."),
+    };
+
+    Ok(parent.canonicalize()?.join(file_name))
+}
+
+fn relative_path(from_dir: &Path, to: &Path) -> PathBuf {
+    let from: Vec<_> = from_dir.components().collect();
+    let to: Vec<_> = to.components().collect();
+
+    let common = from
+        .iter()
+        .zip(to.iter())
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let mut res = PathBuf::new();
+    for _ in common..from.len() {
+        res.push("..");
+    }
+    for component in to[common..].iter() {
+        res.push(component);
+    }
+
+    res
+}
+
+#[cfg(unix)]
+fn create_symlink(link_target: &Path, link: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(link_target, link)
+}
+
+#[cfg(windows)]
+fn create_symlink(link_target: &Path, link: &Path) -> io::Result<()> {
+    std::os::windows::fs::symlink_file(link_target, link)
+}
+
+fn sym_link(victim: &Path, kept: &Path, relative: bool) -> io::Result<u64> {
+    let victim_abs = absolute_path(victim)?;
+    let kept_abs = kept.canonicalize()?;
+
+    if victim_abs == kept_abs {
+        return Ok(0);
+    }
+
+    let link_target = if relative {
+        let victim_dir = victim_abs.parent().unwrap_or_else(|| Path::new("/"));
+        relative_path(victim_dir, &kept_abs)
+    } else {
+        kept_abs
+    };
+
+    let size = freed_size(&fs::metadata(victim)?);
+    replace_with(victim, |temp| create_symlink(&link_target, temp))?;
+    Ok(size)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn relative_path_to_sibling_dir() {
+        assert_eq!(
+            relative_path(Path::new("/data/b"), Path::new("/data/a/x.txt")),
+            PathBuf::from("../a/x.txt")
+        );
+    }
+
+    #[test]
+    fn relative_path_to_nested_dir() {
+        assert_eq!(
+            relative_path(Path::new("/data"), Path::new("/data/a/b/x.txt")),
+            PathBuf::from("a/b/x.txt")
+        );
+    }
+
+    #[test]
+    fn relative_path_from_nested_dir() {
+        assert_eq!(
+            relative_path(Path::new("/data/a/b"), Path::new("/data/x.txt")),
+            PathBuf::from("../../x.txt")
+        );
+    }
+
+    #[test]
+    fn relative_path_in_same_dir() {
+        assert_eq!(
+            relative_path(Path::new("/data/a"), Path::new("/data/a/x.txt")),
+            PathBuf::from("x.txt")
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn hard_link_reclaims_nothing_from_linked_victim() {
+        let dir = std::env::temp_dir().join(format!("fundoubler-actions-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let (kept, victim, other) = (dir.join("kept"), dir.join("victim"), dir.join("other"));
+        fs::write(&kept, "same").unwrap();
+        fs::write(&victim, "same").unwrap();
+        fs::hard_link(&victim, &other).unwrap();
+
+        assert_eq!(hard_link(&victim, &kept).unwrap(), 0);
+        assert_eq!(hard_link(&other, &kept).unwrap(), 4);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,42 @@
+use std::path::Path;
+
+use multimap::MultiMap;
+
+use crate::check::{check_unchanged, CheckOptions};
+use crate::init::ConfigFile;
+use crate::report::read_results;
+
+/// Loads groups from results file and drops files changed since scan.
+/// Groups with less than two unchanged files are dropped too.
+pub fn load_results(cfg: &ConfigFile, path: &Path) -> MultiMap<CheckOptions, CheckOptions> {
+    let groups = match read_results(path) {
+        Ok(g) => g,
+        Err(e) => panic!("Can't read results {}! {}", path.display(), e),
+    };
+
+    let mut res: MultiMap<CheckOptions, CheckOptions> = MultiMap::new();
+
+    for (key, files) in groups {
+        let mut unchanged: Vec<CheckOptions> = Vec::new();
+
+        for file in files {
+            match check_unchanged(&file) {
+                Ok(()) => unchanged.push(file),
+                Err(e) => {
+                    if !cfg.silent_mode {
+                        println!("Skipped {}", e);
+                    }
+                    warn!("Skipped {}", e);
+                }
+            }
+        }
+
+        if unchanged.len() > 1 {
+            res.insert_many(key, unchanged);
+        } else {
+            info!("{} - less than two unchanged files left, skipped", key);
+        }
+    }
+
+    res
+}
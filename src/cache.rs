@@ -0,0 +1,278 @@
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter};
+use std::path::PathBuf;
+use std::time::UNIX_EPOCH;
+
+use serde_derive::{Deserialize, Serialize};
+
+use crate::hash::{hash_file, hash_file_edges, Digests, HashAlgorithm};
+use crate::init::ConfigFile;
+
+/// File state which must be unchanged for the cached digests to stay valid
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileStamp {
+    pub size: u64,
+    pub mtime_secs: u64,
+    pub mtime_nanos: u32,
+    pub inode: u64,
+}
+
+impl FileStamp {
+    pub fn read(path: &str) -> io::Result<Self> {
+        let metadata = fs::metadata(path)?;
+        let mtime = metadata
+            .modified()?
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+
+        Ok(Self {
+            size: metadata.len(),
+            mtime_secs: mtime.as_secs(),
+            mtime_nanos: mtime.subsec_nanos(),
+            inode: inode(&metadata),
+        })
+    }
+}
+
+#[cfg(unix)]
+fn inode(metadata: &fs::Metadata) -> u64 {
+    use std::os::unix::fs::MetadataExt;
+    metadata.ino()
+}
+
+#[cfg(not(unix))]
+fn inode(_metadata: &fs::Metadata) -> u64 {
+    0
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct CacheEntry {
+    stamp: FileStamp,
+    edges: Digests,
+    digests: Digests,
+}
+
+impl CacheEntry {
+    fn new(stamp: FileStamp) -> Self {
+        Self {
+            stamp,
+            edges: Digests::new(),
+            digests: Digests::new(),
+        }
+    }
+}
+
+pub struct HashCache {
+    path: Option<PathBuf>,
+    entries: HashMap<String, CacheEntry>,
+    changed: bool,
+}
+
+impl HashCache {
+    pub fn load(cfg: &ConfigFile) -> Self {
+        let mut cache = Self {
+            path: None,
+            entries: HashMap::new(),
+            changed: false,
+        };
+
+        if cfg.no_cache {
+            return cache;
+        }
+
+        let path = PathBuf::from(&cfg.cache_filename);
+        if !cfg.rebuild_cache && path.exists() {
+            let loaded = File::open(&path).map_err(|e| e.to_string()).and_then(|f| {
+                serde_json::from_reader(BufReader::new(f)).map_err(|e| e.to_string())
+            });
+
+            match loaded {
+                Ok(entries) => cache.entries = entries,
+                Err(e) => {
+                    warn!("Can't load hash cache {}. {}", path.display(), e);
+                    cache.changed = true;
+                }
+            }
+        } else {
+            cache.changed = cfg.rebuild_cache;
+        }
+
+        cache.path = Some(path);
+        cache
+    }
+
+    fn get(&self, path: &str, stamp: &FileStamp) -> Option<&CacheEntry> {
+        self.entries.get(path).filter(|e| &e.stamp == stamp)
+    }
+
+    fn entry(&mut self, path: &str, stamp: &FileStamp) -> &mut CacheEntry {
+        self.changed = true;
+
+        let entry = self
+            .entries
+            .entry(path.to_string())
+            .or_insert_with(|| CacheEntry::new(stamp.clone()));
+        if &entry.stamp != stamp {
+            *entry = CacheEntry::new(stamp.clone());
+        }
+
+        entry
+    }
+
+    /// Partial hash of file, taken from cache if file is unchanged
+    pub fn file_edges(
+        &self,
+        path: &str,
+        edge_size: u64,
+        algo: HashAlgorithm,
+    ) -> io::Result<(FileStamp, String)> {
+        let stamp = FileStamp::read(path)?;
+
+        if let Some(edges) = self.get(path, &stamp).and_then(|e| e.edges.get(&algo)) {
+            return Ok((stamp, edges.clone()));
+        }
+
+        Ok((stamp, hash_file_edges(path, edge_size, algo)?))
+    }
+
+    /// Full digests of file, only missing ones are computed
+    pub fn file_hashes(
+        &self,
+        path: &str,
+        algos: &[HashAlgorithm],
+    ) -> io::Result<(FileStamp, Digests)> {
+        let stamp = FileStamp::read(path)?;
+
+        let mut digests: Digests = match self.get(path, &stamp) {
+            Some(e) => e
+                .digests
+                .iter()
+                .filter(|(algo, _)| algos.contains(algo))
+                .map(|(algo, digest)| (*algo, digest.clone()))
+                .collect(),
+            None => Digests::new(),
+        };
+
+        let missing: Vec<HashAlgorithm> = algos
+            .iter()
+            .filter(|algo| !digests.contains_key(algo))
+            .copied()
+            .collect();
+        if !missing.is_empty() {
+            digests.extend(hash_file(path, &missing)?);
+        }
+
+        Ok((stamp, digests))
+    }
+
+    pub fn store_edges(&mut self, path: &str, stamp: &FileStamp, algo: HashAlgorithm, edges: &str) {
+        if self
+            .get(path, stamp)
+            .and_then(|e| e.edges.get(&algo))
+            .map(String::as_str)
+            == Some(edges)
+        {
+            return;
+        }
+
+        self.entry(path, stamp)
+            .edges
+            .insert(algo, edges.to_string());
+    }
+
+    pub fn store_hashes(&mut self, path: &str, stamp: &FileStamp, digests: &Digests) {
+        if let Some(e) = self.get(path, stamp) {
+            if digests
+                .iter()
+                .all(|(algo, d)| e.digests.get(algo) == Some(d))
+            {
+                return;
+            }
+        }
+
+        self.entry(path, stamp)
+            .digests
+            .extend(digests.iter().map(|(algo, d)| (*algo, d.clone())));
+    }
+
+    pub fn save(&self) {
+        let path = match &self.path {
+            Some(p) if self.changed => p,
+            _ => return,
+        };
+
+        let saved = File::create(path).map_err(|e| e.to_string()).and_then(|f| {
+            serde_json::to_writer(BufWriter::new(f), &self.entries).map_err(|e| e.to_string())
+        });
+
+        if let Err(e) = saved {
+            warn!("Can't save hash cache {}. {}", path.display(), e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cache() -> HashCache {
+        HashCache::load(&ConfigFile {
+            no_cache: true,
+            ..ConfigFile::default()
+        })
+    }
+
+    fn cached(stamp: &FileStamp, path: &str) -> (String, String) {
+        let mut cache = cache();
+        let mut digests = Digests::new();
+        digests.insert(HashAlgorithm::Md5, String::from("cached"));
+        cache.store_hashes(path, stamp, &digests);
+        cache.store_edges(path, stamp, HashAlgorithm::Xxh3, "cached");
+
+        let (_, digests) = cache.file_hashes(path, &[HashAlgorithm::Md5]).unwrap();
+        let (_, edges) = cache.file_edges(path, 4, HashAlgorithm::Xxh3).unwrap();
+        (digests[&HashAlgorithm::Md5].clone(), edges)
+    }
+
+    #[test]
+    fn cached_digests_need_same_stamp() {
+        let dir = std::env::temp_dir().join(format!("fundoubler-cache-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("a.txt");
+        fs::write(&path, "content").unwrap();
+        let path = path.to_string_lossy().to_string();
+
+        let stamp = FileStamp::read(&path).unwrap();
+        assert_eq!(
+            cached(&stamp, &path),
+            (String::from("cached"), String::from("cached"))
+        );
+
+        let changed = [
+            FileStamp {
+                size: stamp.size + 1,
+                ..stamp.clone()
+            },
+            FileStamp {
+                mtime_secs: stamp.mtime_secs + 1,
+                ..stamp.clone()
+            },
+            FileStamp {
+                mtime_nanos: (stamp.mtime_nanos + 1) % 1_000_000_000,
+                ..stamp.clone()
+            },
+            FileStamp {
+                inode: stamp.inode + 1,
+                ..stamp.clone()
+            },
+        ];
+        for old in changed.iter() {
+            let (digest, edges) = cached(old, &path);
+            assert_ne!(digest, "cached", "{:?}", old);
+            assert_ne!(edges, "cached", "{:?}", old);
+        }
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -2,21 +2,27 @@ extern crate chrono;
 
 use std::cmp::Ordering;
 use std::fmt;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
 use std::time::SystemTime;
 
 use chrono::offset::Utc;
 use chrono::DateTime;
 
+use crate::hash::Digests;
 use crate::init::ConfigFile;
 
+const CONTENT_CHUNK_SIZE: usize = 64 * 1024;
+
 #[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
 pub struct CheckOptions {
     pub name: Option<String>,
     pub size: Option<u64>,
     pub created: Option<SystemTime>,
     pub modified: Option<SystemTime>,
-    pub md5: Option<String>,
-    pub sha512: Option<String>,
+    pub digests: Digests,
+    pub content: Option<usize>,
 }
 
 impl CheckOptions {
@@ -26,8 +32,8 @@ impl CheckOptions {
             size: None,
             created: None,
             modified: None,
-            md5: None,
-            sha512: None,
+            digests: Digests::new(),
+            content: None,
         }
     }
 }
@@ -77,61 +83,269 @@ pub fn compare(cfg: &ConfigFile, opt0: &CheckOptions, opt1: &CheckOptions) -> Or
         }
     }
 
-    return Ordering::Equal;
+    Ordering::Equal
+}
+
+/// File lies under one of protected reference paths, which are canonical
+pub fn is_reference(cfg: &ConfigFile, file: &CheckOptions) -> bool {
+    if cfg.reference_paths.is_empty() {
+        return false;
+    }
+
+    let path = Path::new(file.name.as_deref().unwrap_or_default());
+    let parent = match path.parent() {
+        Some(p) if !p.as_os_str().is_empty() => p,
+        _ => Path::new("."),
+    };
+
+    match (parent.canonicalize(), path.file_name()) {
+        (Ok(dir), Some(file_name)) => {
+            let canonical = dir.join(file_name);
+            cfg.reference_paths.iter().any(|r| canonical.starts_with(r))
+        }
+        _ => false,
+    }
+}
+
+/// Compares size and modification time with ones saved at scan
+pub fn check_unchanged(file: &CheckOptions) -> Result<(), String> {
+    let path = file.name.as_deref().unwrap_or_default();
+    let metadata = std::fs::metadata(path).map_err(|e| format!("{} - {}", path, e))?;
+
+    if Some(metadata.len()) != file.size || metadata.modified().ok() != file.modified {
+        return Err(format!("{} changed since scan", path));
+    }
+
+    Ok(())
+}
+
+/// Checks right before destructive action that both files are unchanged since scan
+/// and still have equal content
+pub fn reverify(victim: &CheckOptions, other: Option<&CheckOptions>) -> Result<(), String> {
+    let other = other.ok_or_else(|| "no other copy left".to_string())?;
+
+    check_unchanged(victim)?;
+    check_unchanged(other)?;
+
+    let victim_path = victim.name.as_deref().unwrap_or_default();
+    let other_path = other.name.as_deref().unwrap_or_default();
+
+    // Hard link or the same file seen through a bind mount, removing it loses the data
+    match same_file(victim_path, other_path) {
+        Ok(false) => {}
+        Ok(true) => return Err(format!("same file as {}", other_path)),
+        Err(e) => return Err(format!("can't compare with {} - {}", other_path, e)),
+    }
+
+    match compare_content(victim_path, other_path) {
+        Ok(true) => Ok(()),
+        Ok(false) => Err(format!("content differs from {}", other_path)),
+        Err(e) => Err(format!("can't compare with {} - {}", other_path, e)),
+    }
+}
+
+#[cfg(unix)]
+fn same_file(path0: &str, path1: &str) -> io::Result<bool> {
+    use std::os::unix::fs::MetadataExt;
+
+    let meta0 = std::fs::metadata(path0)?;
+    let meta1 = std::fs::metadata(path1)?;
+
+    Ok(meta0.dev() == meta1.dev() && meta0.ino() == meta1.ino())
+}
+
+#[cfg(not(unix))]
+fn same_file(_path0: &str, _path1: &str) -> io::Result<bool> {
+    Ok(false)
+}
+
+fn read_chunk(file: &mut impl Read, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match file.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(filled)
+}
+
+/// Compares two files chunk by chunk, stops on the first differing block
+pub fn compare_content(path0: &str, path1: &str) -> io::Result<bool> {
+    let mut file0 = File::open(path0)?;
+    let mut file1 = File::open(path1)?;
+
+    if file0.metadata()?.len() != file1.metadata()?.len() {
+        return Ok(false);
+    }
+
+    let mut buf0 = vec![0u8; CONTENT_CHUNK_SIZE];
+    let mut buf1 = vec![0u8; CONTENT_CHUNK_SIZE];
+
+    loop {
+        let len0 = read_chunk(&mut file0, &mut buf0)?;
+        let len1 = read_chunk(&mut file1, &mut buf1)?;
+
+        if len0 != len1 || buf0[..len0] != buf1[..len1] {
+            return Ok(false);
+        }
+
+        if len0 == 0 {
+            return Ok(true);
+        }
+    }
 }
 
 impl fmt::Display for CheckOptions {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let mut first = true;
 
-        if self.name.is_some() {
-            write!(f, "{}", &self.name.as_ref().unwrap())?;
+        if let Some(name) = &self.name {
+            write!(f, "{}", name)?;
             first = false;
         }
 
-        if self.size.is_some() {
+        if let Some(size) = self.size {
             if !first {
                 write!(f, " - ")?;
             }
-            write!(f, "{}", &self.size.as_ref().unwrap())?;
+            write!(f, "{}", size)?;
             first = false;
         }
 
-        if self.created.is_some() {
+        if let Some(created) = self.created {
             if !first {
                 write!(f, ", ")?;
             }
 
-            let datetime: DateTime<Utc> = self.created.unwrap().into();
+            let datetime: DateTime<Utc> = created.into();
             write!(f, "created: {}", datetime.format("%Y-%m-%d][%H:%M:%S"))?;
             first = false;
         }
 
-        if self.modified.is_some() {
+        if let Some(modified) = self.modified {
             if !first {
                 write!(f, ", ")?;
             }
 
-            let datetime: DateTime<Utc> = self.modified.unwrap().into();
+            let datetime: DateTime<Utc> = modified.into();
             write!(f, "modified: {}", datetime.format("%Y-%m-%d][%H:%M:%S"))?;
             first = false;
         }
 
-        if self.md5.is_some() {
+        for (algo, digest) in self.digests.iter() {
             if !first {
                 write!(f, ", ")?;
             }
-            write!(f, "MD5: {}", &self.md5.as_ref().unwrap())?;
+            write!(f, "{}: {}", algo.label(), digest)?;
             first = false;
         }
 
-        if self.sha512.is_some() {
-            if !first {
-                write!(f, ", ")?;
-            }
-            write!(f, "SHA512: {}", &self.md5.as_ref().unwrap())?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::PathBuf;
+
+    use super::*;
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("fundoubler-check-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn scanned(path: &Path) -> CheckOptions {
+        let metadata = fs::metadata(path).unwrap();
+        CheckOptions {
+            name: Some(path.to_string_lossy().to_string()),
+            size: Some(metadata.len()),
+            modified: metadata.modified().ok(),
+            ..CheckOptions::new()
         }
+    }
 
-        Ok(())
+    // Gives at most 3 bytes per read, like pipes and network file systems may do
+    struct ShortReader(Vec<u8>);
+
+    impl Read for ShortReader {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let len = buf.len().min(self.0.len()).min(3);
+            buf[..len].copy_from_slice(&self.0[..len]);
+            self.0.drain(..len);
+            Ok(len)
+        }
+    }
+
+    #[test]
+    fn read_chunk_fills_buffer_from_short_reads() {
+        let mut reader = ShortReader((0..10).collect());
+        let mut buf = [0u8; 8];
+
+        assert_eq!(read_chunk(&mut reader, &mut buf).unwrap(), 8);
+        assert_eq!(buf, [0, 1, 2, 3, 4, 5, 6, 7]);
+        assert_eq!(read_chunk(&mut reader, &mut buf).unwrap(), 2);
+        assert_eq!(&buf[..2], &[8, 9]);
+        assert_eq!(read_chunk(&mut reader, &mut buf).unwrap(), 0);
+    }
+
+    #[test]
+    fn compare_content_checks_every_chunk() {
+        let dir = test_dir("compare");
+        let paths: Vec<String> = ["a", "b", "c", "d"]
+            .iter()
+            .map(|n| dir.join(n).to_string_lossy().to_string())
+            .collect();
+
+        // Longer than one chunk, so the tail is read by the second pass
+        let data = vec![7u8; CONTENT_CHUNK_SIZE + 100];
+        let mut tail_differs = data.clone();
+        *tail_differs.last_mut().unwrap() = 8;
+
+        fs::write(&paths[0], &data).unwrap();
+        fs::write(&paths[1], &data).unwrap();
+        fs::write(&paths[2], &tail_differs).unwrap();
+        fs::write(&paths[3], &data[..data.len() - 1]).unwrap();
+
+        assert!(compare_content(&paths[0], &paths[1]).unwrap());
+        assert!(!compare_content(&paths[0], &paths[2]).unwrap());
+        assert!(!compare_content(&paths[0], &paths[3]).unwrap());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn compare_content_of_empty_files() {
+        let dir = test_dir("empty");
+        let (path0, path1) = (dir.join("a"), dir.join("b"));
+        fs::write(&path0, "").unwrap();
+        fs::write(&path1, "").unwrap();
+
+        assert!(compare_content(&path0.to_string_lossy(), &path1.to_string_lossy()).unwrap());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn reverify_refuses_hard_link_to_other_copy() {
+        let dir = test_dir("hardlink");
+        let (path0, path1, path2) = (dir.join("a"), dir.join("b"), dir.join("c"));
+        fs::write(&path0, "same").unwrap();
+        fs::hard_link(&path0, &path1).unwrap();
+        fs::write(&path2, "same").unwrap();
+
+        let err = reverify(&scanned(&path1), Some(&scanned(&path0))).unwrap_err();
+        assert!(err.starts_with("same file as"), "{}", err);
+        assert_eq!(reverify(&scanned(&path2), Some(&scanned(&path0))), Ok(()));
+
+        fs::remove_dir_all(&dir).unwrap();
     }
 }
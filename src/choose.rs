@@ -0,0 +1,58 @@
+use dialoguer::Select;
+
+use crate::actions::Action;
+use crate::check::{is_reference, CheckOptions};
+use crate::init::ConfigFile;
+
+/// Answer to per-group survivor prompt
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Choice {
+    Keep(usize),
+    SkipGroup,
+    KeepAll,
+    SameRule,
+}
+
+/// Asks which file of a group survives, others get configured action.
+/// `default` is the file selected by keep policies.
+/// `can_skip` offers to postpone the group until other groups are done.
+pub fn choose_kept(
+    cfg: &ConfigFile,
+    action: &Action,
+    files: &[CheckOptions],
+    default: Option<usize>,
+    can_skip: bool,
+) -> Choice {
+    let mut items: Vec<String> = files
+        .iter()
+        .map(|f| {
+            if is_reference(cfg, f) {
+                format!("{} (reference)", f)
+            } else {
+                f.to_string()
+            }
+        })
+        .collect();
+    let mut choices = Vec::new();
+    if can_skip {
+        items.push(String::from("Skip group for now, ask again at the end"));
+        choices.push(Choice::SkipGroup);
+    }
+    items.push(String::from("Keep all"));
+    choices.push(Choice::KeepAll);
+    items.push(String::from("Apply keep rule to this and remaining groups"));
+    choices.push(Choice::SameRule);
+
+    let selected = Select::new()
+        .with_prompt(format!("    Which file to keep (others - {})?", action))
+        .items(&items)
+        .default(default.unwrap_or(0))
+        .interact()
+        .unwrap();
+
+    if selected < files.len() {
+        return Choice::Keep(selected);
+    }
+
+    choices[selected - files.len()]
+}
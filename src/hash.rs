@@ -0,0 +1,156 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::str::FromStr;
+
+use serde_derive::{Deserialize, Serialize};
+use sha2::{Digest, Sha256, Sha512};
+use xxhash_rust::xxh3::Xxh3;
+
+const HASH_BUFFER_SIZE: usize = 256 * 1024;
+
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HashAlgorithm {
+    Md5,
+    Sha256,
+    Sha512,
+    Blake3,
+    Xxh3,
+    Crc32,
+}
+
+/// Hex digests of file by algorithm
+pub type Digests = BTreeMap<HashAlgorithm, String>;
+
+impl HashAlgorithm {
+    pub fn label(&self) -> &'static str {
+        match self {
+            HashAlgorithm::Md5 => "MD5",
+            HashAlgorithm::Sha256 => "SHA256",
+            HashAlgorithm::Sha512 => "SHA512",
+            HashAlgorithm::Blake3 => "BLAKE3",
+            HashAlgorithm::Xxh3 => "XXH3",
+            HashAlgorithm::Crc32 => "CRC32",
+        }
+    }
+
+    fn hasher(&self) -> Hasher {
+        match self {
+            HashAlgorithm::Md5 => Hasher::Md5(md5::Context::new()),
+            HashAlgorithm::Sha256 => Hasher::Sha256(Sha256::new()),
+            HashAlgorithm::Sha512 => Hasher::Sha512(Sha512::new()),
+            HashAlgorithm::Blake3 => Hasher::Blake3(Box::new(blake3::Hasher::new())),
+            HashAlgorithm::Xxh3 => Hasher::Xxh3(Box::new(Xxh3::new())),
+            HashAlgorithm::Crc32 => Hasher::Crc32(crc32fast::Hasher::new()),
+        }
+    }
+}
+
+impl fmt::Display for HashAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.label().to_lowercase())
+    }
+}
+
+impl FromStr for HashAlgorithm {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().replace('-', "").as_str() {
+            "md5" => Ok(HashAlgorithm::Md5),
+            "sha256" => Ok(HashAlgorithm::Sha256),
+            "sha512" => Ok(HashAlgorithm::Sha512),
+            "blake3" => Ok(HashAlgorithm::Blake3),
+            "xxh3" | "xxhash3" => Ok(HashAlgorithm::Xxh3),
+            "crc32" => Ok(HashAlgorithm::Crc32),
+            _ => Err(format!("Unknown hash algorithm {}", s)),
+        }
+    }
+}
+
+enum Hasher {
+    Md5(md5::Context),
+    Sha256(Sha256),
+    Sha512(Sha512),
+    Blake3(Box<blake3::Hasher>),
+    Xxh3(Box<Xxh3>),
+    Crc32(crc32fast::Hasher),
+}
+
+impl Hasher {
+    fn update(&mut self, data: &[u8]) {
+        match self {
+            Hasher::Md5(ctx) => ctx.consume(data),
+            Hasher::Sha256(hasher) => hasher.update(data),
+            Hasher::Sha512(hasher) => hasher.update(data),
+            Hasher::Blake3(hasher) => {
+                hasher.update(data);
+            }
+            Hasher::Xxh3(hasher) => hasher.update(data),
+            Hasher::Crc32(hasher) => hasher.update(data),
+        }
+    }
+
+    fn finalize(self) -> String {
+        match self {
+            Hasher::Md5(ctx) => format!("{:x}", ctx.compute()),
+            Hasher::Sha256(hasher) => format!("{:x}", hasher.finalize()),
+            Hasher::Sha512(hasher) => format!("{:x}", hasher.finalize()),
+            Hasher::Blake3(hasher) => hasher.finalize().to_hex().to_string(),
+            Hasher::Xxh3(hasher) => format!("{:016x}", hasher.digest()),
+            Hasher::Crc32(hasher) => format!("{:08x}", hasher.finalize()),
+        }
+    }
+}
+
+/// Computes requested digests in one pass with constant memory usage
+pub fn hash_file(path: &str, algos: &[HashAlgorithm]) -> io::Result<Digests> {
+    let mut file = File::open(path)?;
+
+    let mut hashers: Vec<(HashAlgorithm, Hasher)> =
+        algos.iter().map(|algo| (*algo, algo.hasher())).collect();
+
+    let mut buf = vec![0u8; HASH_BUFFER_SIZE];
+    loop {
+        let len = match file.read(&mut buf) {
+            Ok(0) => break,
+            Ok(len) => len,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+
+        for (_, hasher) in hashers.iter_mut() {
+            hasher.update(&buf[..len]);
+        }
+    }
+
+    Ok(hashers
+        .into_iter()
+        .map(|(algo, hasher)| (algo, hasher.finalize()))
+        .collect())
+}
+
+/// Hashes only first and last `edge_size` bytes of file, used to prefilter candidates
+pub fn hash_file_edges(path: &str, edge_size: u64, algo: HashAlgorithm) -> io::Result<String> {
+    let mut file = File::open(path)?;
+    let file_size = file.metadata()?.len();
+
+    let mut hasher = algo.hasher();
+    let mut buf = Vec::new();
+
+    (&mut file).take(edge_size).read_to_end(&mut buf)?;
+    hasher.update(&buf);
+
+    if file_size > edge_size {
+        let tail_start = std::cmp::max(edge_size, file_size - edge_size);
+        file.seek(SeekFrom::Start(tail_start))?;
+
+        buf.clear();
+        file.take(edge_size).read_to_end(&mut buf)?;
+        hasher.update(&buf);
+    }
+
+    Ok(hasher.finalize())
+}
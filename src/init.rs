@@ -10,30 +10,61 @@ use std::time::SystemTime;
 use chrono::{Datelike, Utc};
 use confy::load_path;
 use humantime::Timestamp;
+use serde::{Deserialize as _, Deserializer};
 use serde_derive::{Deserialize, Serialize};
 use simple_log::LogConfigBuilder;
 use structopt::StructOpt;
 
+use crate::hash::HashAlgorithm;
+use crate::report::OutputFormat;
+
 const DEFAULT_START: &str = ".";
 
 const DEFAULT_CFG: &str = "fundoubler.cfg";
 const DEFAULT_OUT: &str = ".fundoubler%DATE%.res";
 const DEFAULT_LOG: &str = "./fundoubler%DATE%.log";
+const DEFAULT_CACHE: &str = "fundoubler.cache";
+const DEFAULT_JOURNAL: &str = "./fundoubler%DATE%.journal";
 const DATE_TEMPLATE: &str = "%DATE%";
 
 const DEFAULT_FIRST_N: usize = 100;
 
+// Old configs have single path_start
+fn one_or_many_paths<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<PathBuf>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(PathBuf),
+        Many(Vec<PathBuf>),
+    }
+
+    Ok(match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(path) => vec![path],
+        OneOrMany::Many(paths) => paths,
+    })
+}
+
 /*#[serde_as]*/
 #[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
 pub struct ConfigFile {
     pub global_verbose: u8,
     pub hide_config: bool,
     pub debug: bool,
+    pub dry_run: bool,
     pub debug_config: bool,
     pub show_options_only: bool,
 
     pub delete: bool,
     pub force_delete: bool,
+    pub review: bool,
+    pub tui: bool,
+    pub hardlink: bool,
+    pub symlink: bool,
+    pub relative_symlinks: bool,
+    pub reflink: bool,
+    pub move_to: Option<PathBuf>,
+    pub keep_policies: Vec<String>,
     pub silent_mode: bool,
 
     pub size: bool,
@@ -42,6 +73,8 @@ pub struct ConfigFile {
     pub date_modified: bool,
     pub hash_md5: bool,
     pub hash_sha512: bool,
+    pub hash_algos: Vec<HashAlgorithm>,
+    pub partial_hash_algo: HashAlgorithm,
     pub content: bool,
 
     pub min_size: u64,
@@ -56,6 +89,11 @@ pub struct ConfigFile {
 
     pub first_n: usize,
 
+    pub jobs: usize,
+
+    pub no_cache: bool,
+    pub rebuild_cache: bool,
+
     pub sort_res_name_asc: bool,
     pub sort_res_name_desc: bool,
     pub sort_res_size_asc: bool,
@@ -65,9 +103,19 @@ pub struct ConfigFile {
     pub sort_res_mdate_asc: bool,
     pub sort_res_mdate_desc: bool,
 
-    pub path_start: Option<PathBuf>,
+    #[serde(alias = "path_start", deserialize_with = "one_or_many_paths")]
+    pub paths_start: Vec<PathBuf>,
+    pub reference_paths: Vec<PathBuf>,
     pub out_filename: Option<PathBuf>,
+    pub format: OutputFormat,
     pub log_filename: String,
+    pub cache_filename: String,
+    pub journal_filename: String,
+
+    #[serde(skip)]
+    pub restore_journal: Option<PathBuf>,
+    #[serde(skip)]
+    pub apply_results: Option<PathBuf>,
 }
 
 impl Default for ConfigFile {
@@ -76,11 +124,20 @@ impl Default for ConfigFile {
             global_verbose: 0,
             hide_config: false,
             debug: false,
+            dry_run: false,
             debug_config: false,
             show_options_only: false,
 
             delete: false,
             force_delete: false,
+            review: false,
+            tui: false,
+            hardlink: false,
+            symlink: false,
+            relative_symlinks: false,
+            reflink: false,
+            move_to: None,
+            keep_policies: Vec::new(),
             silent_mode: false,
 
             size: false,
@@ -89,6 +146,8 @@ impl Default for ConfigFile {
             date_modified: false,
             hash_md5: false,
             hash_sha512: false,
+            hash_algos: Vec::new(),
+            partial_hash_algo: HashAlgorithm::Xxh3,
             content: false,
 
             min_size: 0,
@@ -103,6 +162,11 @@ impl Default for ConfigFile {
 
             first_n: DEFAULT_FIRST_N,
 
+            jobs: 0,
+
+            no_cache: false,
+            rebuild_cache: false,
+
             sort_res_name_asc: false,
             sort_res_name_desc: false,
             sort_res_size_asc: false,
@@ -112,13 +176,35 @@ impl Default for ConfigFile {
             sort_res_mdate_asc: false,
             sort_res_mdate_desc: false,
 
-            path_start: Some(PathBuf::from(DEFAULT_START)),
+            paths_start: vec![PathBuf::from(DEFAULT_START)],
+            reference_paths: Vec::new(),
             out_filename: Some(PathBuf::from(DEFAULT_OUT)),
+            format: OutputFormat::Text,
             log_filename: DEFAULT_LOG.to_string(),
+            cache_filename: "".to_string(),
+            journal_filename: DEFAULT_JOURNAL.to_string(),
+
+            restore_journal: None,
+            apply_results: None,
         }
     }
 }
 
+#[derive(StructOpt, Debug)]
+pub enum Command {
+    /// Undo moves and link replacements recorded in journal,
+    /// or moves recorded in manifest of quarantine directory
+    Restore {
+        #[structopt(parse(from_os_str))]
+        journal: PathBuf,
+    },
+    /// Re-verify groups from JSON results file and act on unneeded doubles
+    Apply {
+        #[structopt(parse(from_os_str))]
+        results: PathBuf,
+    },
+}
+
 #[derive(StructOpt, Debug)]
 #[structopt(name = "spamer")]
 pub struct Options {
@@ -139,6 +225,10 @@ pub struct Options {
     #[structopt(long = "debug")]
     pub debug: bool,
 
+    /// Show what would be done with doubles, change nothing
+    #[structopt(long = "dry-run")]
+    pub dry_run: bool,
+
     /// Show options only - no real work
     #[structopt(long = "show-options-only")]
     pub show_options_only: bool,
@@ -155,6 +245,39 @@ pub struct Options {
     #[structopt(short = "f", long = "force-delete")]
     pub force_delete: bool,
 
+    /// Mark unneeded doubles as keep/del/link in $EDITOR instead of confirming them one by one
+    #[structopt(long = "review")]
+    pub review: bool,
+
+    /// Review and mark unneeded doubles in full-screen terminal UI
+    #[structopt(long = "tui")]
+    pub tui: bool,
+
+    /// Replace unneeded doubles with hard links to kept file
+    #[structopt(long = "hardlink")]
+    pub hardlink: bool,
+
+    /// Replace unneeded doubles with symbolic links to kept file
+    #[structopt(long = "symlink")]
+    pub symlink: bool,
+
+    /// Make symbolic links relative to link location
+    #[structopt(long = "relative-symlinks")]
+    pub relative_symlinks: bool,
+
+    /// Share data of doubles with kept file on copy-on-write file systems (Btrfs, XFS)
+    #[structopt(long = "reflink")]
+    pub reflink: bool,
+
+    /// Move unneeded doubles to quarantine directory, mirroring their paths
+    #[structopt(long = "move-to", parse(from_os_str))]
+    pub move_to: Option<PathBuf>,
+
+    /// Which file of a group to keep: oldest, newest, shortest-path, longest-path, shallowest,
+    /// regex:<pattern>, dir:<path>. Repeat to add tie-breakers
+    #[structopt(long = "keep", number_of_values = 1)]
+    pub keep: Vec<String>,
+
     /// Silent mode
     #[structopt(short = "S", long = "silent")]
     pub silent_mode: bool,
@@ -179,6 +302,14 @@ pub struct Options {
     #[structopt(long = "sha512")]
     pub hash_sha512: bool,
 
+    /// Check files by hashes (md5, sha256, sha512, blake3, xxh3, crc32), comma separated
+    #[structopt(long = "hash-algo", use_delimiter = true, number_of_values = 1)]
+    pub hash_algo: Vec<HashAlgorithm>,
+
+    /// Hash algorithm to prefilter candidates by first and last blocks
+    #[structopt(long = "partial-hash-algo")]
+    pub partial_hash_algo: Option<HashAlgorithm>,
+
     /// Check files by content
     #[structopt(short = "t", long)]
     pub content: bool,
@@ -223,6 +354,22 @@ pub struct Options {
     #[structopt(short = "F", long = "first-n", default_value = "0")]
     pub first_n: usize,
 
+    /// Number of hashing threads, 0 for all CPUs
+    #[structopt(short = "j", long = "jobs", default_value = "0")]
+    pub jobs: usize,
+
+    /// Hash cache file, next to config file if not present
+    #[structopt(long = "cache-file", default_value = "")]
+    pub cache_file: String,
+
+    /// Don't use hash cache
+    #[structopt(long = "no-cache")]
+    pub no_cache: bool,
+
+    /// Drop hash cache and fill it again
+    #[structopt(long = "rebuild-cache")]
+    pub rebuild_cache: bool,
+
     /// Sort results by name
     #[structopt(long = "sort-name")]
     pub sort_res_name_asc: bool,
@@ -259,13 +406,28 @@ pub struct Options {
     #[structopt(short, long, default_value = "")]
     pub log: String,
 
-    /// start path, . if not present
-    #[structopt(parse(from_os_str))]
-    path_start: Option<PathBuf>,
+    /// Journal of performed actions
+    #[structopt(long, default_value = "")]
+    pub journal: String,
 
-    /// output path, stdout if not present
-    #[structopt(parse(from_os_str))]
+    /// Protected reference path, its files are never deleted (repeatable)
+    #[structopt(long = "reference", parse(from_os_str), number_of_values = 1)]
+    pub reference: Vec<PathBuf>,
+
+    /// Output path
+    #[structopt(short, long, parse(from_os_str))]
     out: Option<PathBuf>,
+
+    /// Results format: text, json, csv, tsv, html
+    #[structopt(long)]
+    format: Option<OutputFormat>,
+
+    /// Start paths, . if not present
+    #[structopt(parse(from_os_str))]
+    paths_start: Vec<PathBuf>,
+
+    #[structopt(subcommand)]
+    command: Option<Command>,
 }
 
 pub fn init() -> Result<ConfigFile, confy::ConfyError> {
@@ -280,7 +442,7 @@ pub fn init() -> Result<ConfigFile, confy::ConfyError> {
         None => PathBuf::from(DEFAULT_CFG),
     };
 
-    let mut cfg: ConfigFile = load_path(configfile)?;
+    let mut cfg: ConfigFile = load_path(&configfile)?;
 
     cfg.silent_mode = options.silent_mode || cfg.silent_mode;
 
@@ -296,9 +458,60 @@ pub fn init() -> Result<ConfigFile, confy::ConfyError> {
 
     cfg.debug = options.debug || cfg.debug;
 
+    // Debug run never changed files, keep it so
+    cfg.dry_run = options.dry_run || cfg.dry_run || cfg.debug;
+
     cfg.show_options_only = options.show_options_only || cfg.show_options_only;
 
-    cfg.delete = options.delete || cfg.delete;
+    cfg.hardlink = options.hardlink || cfg.hardlink;
+    cfg.relative_symlinks = options.relative_symlinks || cfg.relative_symlinks;
+    cfg.symlink = options.symlink || cfg.symlink || cfg.relative_symlinks;
+
+    cfg.reflink = options.reflink || cfg.reflink;
+
+    cfg.move_to = match options.move_to.or(cfg.move_to) {
+        None => None,
+        // Directory is created on first move, so dry run touches nothing
+        Some(dir) => match dir.canonicalize().or_else(|_| std::path::absolute(&dir)) {
+            Ok(c) => Some(c),
+            Err(e) => panic!("Can't open quarantine directory {} - {}", dir.display(), e),
+        },
+    };
+
+    if [
+        cfg.hardlink,
+        cfg.symlink,
+        cfg.reflink,
+        cfg.move_to.is_some(),
+    ]
+    .iter()
+    .filter(|x| **x)
+    .count()
+        > 1
+    {
+        panic!("Can't use more than one of hard links, symbolic links, reflinks and moving simultaneously!");
+    }
+
+    cfg.review = options.review || cfg.review;
+    cfg.tui = options.tui || cfg.tui;
+
+    if cfg.review && cfg.tui {
+        panic!("Can't review doubles in editor and terminal UI simultaneously!");
+    }
+
+    cfg.delete = options.delete
+        || cfg.delete
+        || cfg.hardlink
+        || cfg.symlink
+        || cfg.reflink
+        || cfg.move_to.is_some()
+        || cfg.review
+        || cfg.tui
+        || matches!(options.command, Some(Command::Apply { .. }));
+    if !options.keep.is_empty() {
+        cfg.keep_policies = options.keep;
+    }
+
     cfg.force_delete = (options.force_delete || cfg.force_delete) && cfg.delete;
 
     cfg.name = options.name || cfg.name;
@@ -314,6 +527,21 @@ pub fn init() -> Result<ConfigFile, confy::ConfyError> {
         true => true,
         false => options.hash_sha512 || cfg.hash_sha512,
     };
+
+    if !options.hash_algo.is_empty() {
+        cfg.hash_algos = options.hash_algo;
+    }
+    if cfg.hash_md5 {
+        cfg.hash_algos.push(HashAlgorithm::Md5);
+    }
+    if cfg.hash_sha512 {
+        cfg.hash_algos.push(HashAlgorithm::Sha512);
+    }
+    cfg.hash_algos.sort();
+    cfg.hash_algos.dedup();
+
+    cfg.partial_hash_algo = options.partial_hash_algo.unwrap_or(cfg.partial_hash_algo);
+
     cfg.content = options.content || cfg.content;
 
     cfg.min_size = match options.min_size {
@@ -326,25 +554,25 @@ pub fn init() -> Result<ConfigFile, confy::ConfyError> {
         s => s,
     };
 
-    cfg.min_createdate = if options.min_createdate == "" {
+    cfg.min_createdate = if options.min_createdate.is_empty() {
         cfg.min_createdate
     } else {
         options.min_createdate
     };
 
-    cfg.max_createdate = if options.max_createdate == "" {
+    cfg.max_createdate = if options.max_createdate.is_empty() {
         cfg.max_createdate
     } else {
         options.max_createdate
     };
 
-    cfg.min_moddate = if options.min_moddate == "" {
+    cfg.min_moddate = if options.min_moddate.is_empty() {
         cfg.min_moddate
     } else {
         options.min_moddate
     };
 
-    cfg.max_moddate = if options.max_moddate == "" {
+    cfg.max_moddate = if options.max_moddate.is_empty() {
         cfg.max_moddate
     } else {
         options.max_moddate
@@ -355,6 +583,27 @@ pub fn init() -> Result<ConfigFile, confy::ConfyError> {
         s => s,
     };
 
+    cfg.jobs = match options.jobs {
+        0 => cfg.jobs,
+        s => s,
+    };
+
+    cfg.no_cache = options.no_cache || cfg.no_cache;
+    cfg.rebuild_cache = options.rebuild_cache || cfg.rebuild_cache;
+
+    cfg.cache_filename = match options.cache_file.is_empty() {
+        true => cfg.cache_filename,
+        false => options.cache_file,
+    };
+
+    if cfg.cache_filename.is_empty() {
+        cfg.cache_filename = configfile
+            .with_file_name(DEFAULT_CACHE)
+            .into_os_string()
+            .into_string()
+            .unwrap();
+    }
+
     cfg.sort_res_name_asc = options.sort_res_name_asc || cfg.sort_res_name_asc;
     cfg.sort_res_name_desc = options.sort_res_name_desc || cfg.sort_res_name_desc;
     cfg.sort_res_size_asc = options.sort_res_size_asc || cfg.sort_res_size_asc;
@@ -385,16 +634,29 @@ pub fn init() -> Result<ConfigFile, confy::ConfyError> {
         false => options.name_filter,
     };
 
-    cfg.path_start = match options.path_start {
-        None => cfg.path_start,
-        Some(x) => Some(x),
-    };
+    if !options.paths_start.is_empty() {
+        cfg.paths_start = options.paths_start;
+    }
+
+    cfg.reference_paths.extend(options.reference);
+    cfg.reference_paths = cfg
+        .reference_paths
+        .iter()
+        .map(|p| match p.canonicalize() {
+            Ok(c) => c,
+            Err(e) => panic!("Can't open reference path {} - {}", p.display(), e),
+        })
+        .collect();
+    cfg.reference_paths.sort();
+    cfg.reference_paths.dedup();
 
     cfg.out_filename = match options.out {
         None => cfg.out_filename,
         Some(x) => Some(x),
     };
 
+    cfg.format = options.format.unwrap_or(cfg.format);
+
     if cfg.out_filename.is_some() {
         let now = Utc::now();
         let date_str = format!("{}{:02}{:02}", now.year(), now.month(), now.day());
@@ -413,6 +675,23 @@ pub fn init() -> Result<ConfigFile, confy::ConfyError> {
         false => options.log,
     };
 
+    cfg.journal_filename = match options.journal.is_empty() {
+        true => cfg.journal_filename,
+        false => options.journal,
+    };
+
+    if !cfg.journal_filename.is_empty() {
+        let now = Utc::now();
+        let date_str = format!("{}{:02}{:02}", now.year(), now.month(), now.day());
+        cfg.journal_filename = cfg.journal_filename.replace(DATE_TEMPLATE, &date_str);
+    }
+
+    match options.command {
+        Some(Command::Restore { journal }) => cfg.restore_journal = Some(journal),
+        Some(Command::Apply { results }) => cfg.apply_results = Some(results),
+        None => {}
+    }
+
     if !cfg.log_filename.is_empty() {
         let now = Utc::now();
         let date_str = format!("{}{:02}{:02}", now.year(), now.month(), now.day());
@@ -425,7 +704,7 @@ pub fn init() -> Result<ConfigFile, confy::ConfyError> {
 pub fn init_log(log_filename: &String) -> Result<(), String> {
     let log = LogConfigBuilder::builder()
         .path(log_filename)
-        .size(1 * 100)
+        .size(100)
         .roll_count(10)
         .level("warn")
         .output_file()
@@ -443,3 +722,23 @@ pub fn convert_string_to_system_time(s: &String, err_msg: &str) -> SystemTime {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_old_single_start_path() {
+        let cfg: ConfigFile = serde_json::from_str(r#"{"path_start": "/data"}"#).unwrap();
+        assert_eq!(cfg.paths_start, vec![PathBuf::from("/data")]);
+    }
+
+    #[test]
+    fn reads_start_path_list() {
+        let cfg: ConfigFile = serde_json::from_str(r#"{"paths_start": ["/a", "/b"]}"#).unwrap();
+        assert_eq!(
+            cfg.paths_start,
+            vec![PathBuf::from("/a"), PathBuf::from("/b")]
+        );
+    }
+}
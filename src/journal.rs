@@ -0,0 +1,105 @@
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+
+use chrono::Utc;
+use serde::de::DeserializeOwned;
+use serde_derive::{Deserialize, Serialize};
+
+use crate::hash::Digests;
+use crate::init::ConfigFile;
+
+/// One performed action in undo journal
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct JournalEntry {
+    pub action: String,
+    pub original: String,
+    pub kept: Option<String>,
+    pub moved_to: Option<String>,
+    pub hash: Digests,
+    pub size: u64,
+    pub time: String,
+}
+
+impl JournalEntry {
+    pub fn new(action: &str, original: String, kept: Option<String>, hash: &Digests) -> Self {
+        Self {
+            action: action.to_string(),
+            original,
+            kept,
+            moved_to: None,
+            hash: hash.clone(),
+            size: 0,
+            time: Utc::now().to_rfc3339(),
+        }
+    }
+}
+
+/// Appends performed actions to JSON lines file
+pub struct Journal {
+    file: Option<File>,
+    filename: String,
+}
+
+impl Journal {
+    pub fn open(cfg: &ConfigFile) -> Self {
+        if cfg.journal_filename.is_empty() || cfg.dry_run {
+            return Self {
+                file: None,
+                filename: String::new(),
+            };
+        }
+
+        let file = match OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&cfg.journal_filename)
+        {
+            Ok(f) => f,
+            Err(e) => panic!("Can't open journal file {}! {}", cfg.journal_filename, e),
+        };
+
+        Self {
+            file: Some(file),
+            filename: cfg.journal_filename.clone(),
+        }
+    }
+
+    pub fn record(&mut self, entry: &JournalEntry) {
+        let file = match self.file.as_mut() {
+            Some(f) => f,
+            None => return,
+        };
+
+        let written = serde_json::to_string(entry)
+            .map_err(|e| e.to_string())
+            .and_then(|line| writeln!(file, "{}", line).map_err(|e| e.to_string()));
+
+        if let Err(e) = written {
+            println!("Can't write to journal {} - {}", self.filename, e);
+            warn!("Can't write to journal {} - {}", self.filename, e);
+        }
+    }
+}
+
+/// Reads JSON lines file, empty lines are skipped
+pub fn read_json_lines<T: DeserializeOwned>(path: &Path) -> Result<Vec<T>, String> {
+    let file = File::open(path).map_err(|e| e.to_string())?;
+
+    let mut entries = Vec::new();
+    for (idx, line) in BufReader::new(file).lines().enumerate() {
+        let line = line.map_err(|e| e.to_string())?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let entry = serde_json::from_str(&line).map_err(|e| format!("line {}: {}", idx + 1, e))?;
+        entries.push(entry);
+    }
+
+    Ok(entries)
+}
+
+pub fn read_journal(path: &Path) -> Result<Vec<JournalEntry>, String> {
+    read_json_lines(path)
+}
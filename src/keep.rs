@@ -0,0 +1,153 @@
+use std::cmp::{Ordering, Reverse};
+use std::path::{Path, PathBuf};
+
+use regex::Regex;
+
+use crate::actions::absolute_path;
+use crate::check::{is_reference, CheckOptions};
+use crate::init::ConfigFile;
+
+/// Rule choosing which file of a group survives, next rules break ties
+#[derive(Clone, Debug)]
+pub enum KeepPolicy {
+    Oldest,
+    Newest,
+    ShortestPath,
+    LongestPath,
+    Shallowest,
+    Regex(Regex),
+    Dir(PathBuf),
+}
+
+impl KeepPolicy {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        if let Some(pattern) = s.strip_prefix("regex:") {
+            return Regex::new(pattern)
+                .map(KeepPolicy::Regex)
+                .map_err(|e| format!("Can't parse keep regexp {} - {}", pattern, e));
+        }
+
+        if let Some(dir) = s.strip_prefix("dir:") {
+            return Path::new(dir)
+                .canonicalize()
+                .map(KeepPolicy::Dir)
+                .map_err(|e| format!("Can't open preferred directory {} - {}", dir, e));
+        }
+
+        match s {
+            "oldest" => Ok(KeepPolicy::Oldest),
+            "newest" => Ok(KeepPolicy::Newest),
+            "shortest-path" => Ok(KeepPolicy::ShortestPath),
+            "longest-path" => Ok(KeepPolicy::LongestPath),
+            "shallowest" => Ok(KeepPolicy::Shallowest),
+            _ => Err(format!("Unknown keep policy {}", s)),
+        }
+    }
+
+    // Less means more preferable to keep
+    fn compare(&self, file0: &CheckOptions, file1: &CheckOptions) -> Ordering {
+        let path0 = file0.name.as_deref().unwrap_or_default();
+        let path1 = file1.name.as_deref().unwrap_or_default();
+
+        match self {
+            KeepPolicy::Oldest => option_first(file0.modified, file1.modified),
+            KeepPolicy::Newest => {
+                option_first(file0.modified.map(Reverse), file1.modified.map(Reverse))
+            }
+            KeepPolicy::ShortestPath => path0.len().cmp(&path1.len()),
+            KeepPolicy::LongestPath => path1.len().cmp(&path0.len()),
+            KeepPolicy::Shallowest => depth(path0).cmp(&depth(path1)),
+            KeepPolicy::Regex(re) => re.is_match(path1).cmp(&re.is_match(path0)),
+            KeepPolicy::Dir(dir) => inside(path1, dir).cmp(&inside(path0, dir)),
+        }
+    }
+}
+
+// Known values go before unknown ones
+fn option_first<T: Ord>(v0: Option<T>, v1: Option<T>) -> Ordering {
+    match (v0, v1) {
+        (Some(a), Some(b)) => a.cmp(&b),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => Ordering::Equal,
+    }
+}
+
+fn depth(path: &str) -> usize {
+    absolute_path(Path::new(path))
+        .map(|p| p.components().count())
+        .unwrap_or_else(|_| Path::new(path).components().count())
+}
+
+fn inside(path: &str, dir: &Path) -> bool {
+    absolute_path(Path::new(path))
+        .map(|p| p.starts_with(dir))
+        .unwrap_or(false)
+}
+
+pub fn parse_policies(list: &[String]) -> Result<Vec<KeepPolicy>, String> {
+    list.iter().map(|s| KeepPolicy::parse(s)).collect()
+}
+
+/// File which is kept in any case and becomes the target of links.
+/// Reference files are preferred, then policies in order, then order of group.
+pub fn select_kept<'a>(
+    cfg: &ConfigFile,
+    policies: &[KeepPolicy],
+    files: &'a [CheckOptions],
+) -> Option<&'a CheckOptions> {
+    let reference_mode = !cfg.reference_paths.is_empty();
+
+    files
+        .iter()
+        .filter(|f| !reference_mode || is_reference(cfg, f))
+        .min_by(|f0, f1| {
+            policies
+                .iter()
+                .map(|p| p.compare(f0, f1))
+                .find(|o| *o != Ordering::Equal)
+                .unwrap_or(Ordering::Equal)
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_known_policies() {
+        assert!(matches!(
+            KeepPolicy::parse("oldest"),
+            Ok(KeepPolicy::Oldest)
+        ));
+        assert!(matches!(
+            KeepPolicy::parse("shortest-path"),
+            Ok(KeepPolicy::ShortestPath)
+        ));
+        assert!(matches!(
+            KeepPolicy::parse("regex:^/photos/"),
+            Ok(KeepPolicy::Regex(_))
+        ));
+    }
+
+    #[test]
+    fn rejects_unknown_policy_and_bad_regex() {
+        assert!(KeepPolicy::parse("youngest").is_err());
+        assert!(KeepPolicy::parse("regex:(").is_err());
+        assert!(KeepPolicy::parse("dir:/no/such/fundoubler/dir").is_err());
+    }
+
+    #[test]
+    fn later_policies_break_ties() {
+        let file = |name: &str| CheckOptions {
+            name: Some(name.to_string()),
+            ..CheckOptions::new()
+        };
+        let files = [file("/b/long/x"), file("/a/x"), file("/c/x")];
+        let policies =
+            parse_policies(&["shortest-path".to_string(), "regex:^/c/".to_string()]).unwrap();
+
+        let kept = select_kept(&ConfigFile::default(), &policies, &files);
+        assert_eq!(kept, Some(&files[2]));
+    }
+}
@@ -2,28 +2,95 @@
 extern crate simple_log;
 
 use std::cmp::Ordering;
+use std::collections::{HashMap, VecDeque};
 use std::fs::File;
-use std::io::{Read, Write};
+use std::hash::Hash;
+use std::io::Write;
+use std::path::{Path, PathBuf};
 use std::time::SystemTime;
 
-use dialoguer::Confirm;
 use itertools::Itertools;
 use multimap::MultiMap;
 use regex::Regex;
-use sha2::{Digest, Sha512};
-use walkdir::WalkDir;
+use walkdir::{DirEntry, WalkDir};
 
 use init::{convert_string_to_system_time, init_log, ConfigFile};
 
-use crate::check::{compare, CheckOptions};
-
+use crate::actions::{absolute_path, Action};
+use crate::cache::HashCache;
+use crate::check::{compare, compare_content, is_reference, reverify, CheckOptions};
+use crate::choose::{choose_kept, Choice};
+use crate::hash::Digests;
+use crate::journal::{Journal, JournalEntry};
+use crate::keep::{parse_policies, select_kept, KeepPolicy};
+use crate::pool::{map_parallel, stream_parallel};
+use crate::report::render;
+use crate::review::{review, Mark};
+use crate::stats::{ScanStats, Summary};
+
+mod actions;
+mod apply;
+mod cache;
 mod check;
+mod choose;
+mod hash;
 mod init;
+mod journal;
+mod keep;
+mod pool;
+mod quarantine;
+mod reflink;
+mod report;
+mod restore;
+mod review;
+mod stats;
+mod tui;
+
+const PARTIAL_HASH_SIZE: u64 = 16 * 1024;
+
+// Nested or repeated roots are skipped so no file is scanned twice
+fn scan_roots(cfg: &ConfigFile) -> Vec<PathBuf> {
+    let mut roots: Vec<(PathBuf, PathBuf)> = Vec::new();
+    for root in cfg.paths_start.iter().chain(cfg.reference_paths.iter()) {
+        match root.canonicalize() {
+            Ok(canonical) => roots.push((canonical, root.clone())),
+            Err(e) => {
+                println!("Can't open start path {} - {}", root.display(), e);
+                warn!("Can't open start path {} - {}", root.display(), e);
+            }
+        }
+    }
+    roots.sort();
 
-fn analyze(cfg: &ConfigFile) -> MultiMap<CheckOptions, CheckOptions> {
-    let mut files: MultiMap<check::CheckOptions, CheckOptions> = MultiMap::new();
+    let mut res: Vec<(PathBuf, PathBuf)> = Vec::new();
+    for (canonical, root) in roots {
+        if res.iter().any(|(kept, _)| canonical.starts_with(kept)) {
+            warn!("Start path {} is already scanned", root.display());
+            continue;
+        }
+        res.push((canonical, root));
+    }
+
+    res.into_iter().map(|(_, root)| root).collect()
+}
 
-    let min_create_date = if cfg.min_createdate == "" {
+// Moved doubles must not be found again as doubles of their originals
+fn in_quarantine(cfg: &ConfigFile, entry: &DirEntry) -> bool {
+    match &cfg.move_to {
+        Some(dir) if entry.file_type().is_dir() => absolute_path(entry.path())
+            .map(|p| p == *dir)
+            .unwrap_or(false),
+        _ => false,
+    }
+}
+
+// Walks start paths, files passing filters are given to `on_file` with their grouping key
+fn walk(
+    cfg: &ConfigFile,
+    stats: &mut ScanStats,
+    mut on_file: impl FnMut(CheckOptions, CheckOptions),
+) {
+    let min_create_date = if cfg.min_createdate.is_empty() {
         None
     } else {
         Some(convert_string_to_system_time(
@@ -32,7 +99,7 @@ fn analyze(cfg: &ConfigFile) -> MultiMap<CheckOptions, CheckOptions> {
         ))
     };
 
-    let max_create_date = if cfg.max_createdate == "" {
+    let max_create_date = if cfg.max_createdate.is_empty() {
         None
     } else {
         Some(convert_string_to_system_time(
@@ -41,7 +108,7 @@ fn analyze(cfg: &ConfigFile) -> MultiMap<CheckOptions, CheckOptions> {
         ))
     };
 
-    let min_mod_date = if cfg.min_moddate == "" {
+    let min_mod_date = if cfg.min_moddate.is_empty() {
         None
     } else {
         Some(convert_string_to_system_time(
@@ -50,7 +117,7 @@ fn analyze(cfg: &ConfigFile) -> MultiMap<CheckOptions, CheckOptions> {
         ))
     };
 
-    let max_mod_date = if cfg.max_moddate == "" {
+    let max_mod_date = if cfg.max_moddate.is_empty() {
         None
     } else {
         Some(convert_string_to_system_time(
@@ -59,7 +126,7 @@ fn analyze(cfg: &ConfigFile) -> MultiMap<CheckOptions, CheckOptions> {
         ))
     };
 
-    let re = if cfg.name_filter == "" {
+    let re = if cfg.name_filter.is_empty() {
         None
     } else {
         match Regex::new(cfg.name_filter.as_str()) {
@@ -73,22 +140,19 @@ fn analyze(cfg: &ConfigFile) -> MultiMap<CheckOptions, CheckOptions> {
         }
     };
 
-    for entry in WalkDir::new(
-        cfg.path_start
-            .clone()
-            .expect("")
-            .into_os_string()
-            .into_string()
-            .unwrap(),
-    )
-    .into_iter()
-    .filter_map(Result::ok)
-    .filter(|e| !e.file_type().is_dir())
+    for entry in scan_roots(cfg)
+        .into_iter()
+        .flat_map(|root| {
+            WalkDir::new(root)
+                .sort_by_file_name()
+                .into_iter()
+                .filter_entry(|e| !in_quarantine(cfg, e))
+        })
+        .filter_map(Result::ok)
+        .filter(|e| !e.file_type().is_dir() && !e.path_is_symlink())
     {
         let mut file_date_c: Option<SystemTime> = None;
         let mut file_date_m: Option<SystemTime> = None;
-        let mut file_md5: Option<String> = None;
-        let mut file_sha512: Option<String> = None;
 
         let file_name = String::from(entry.file_name().to_string_lossy());
         let file_path = String::from(entry.path().to_string_lossy());
@@ -102,6 +166,7 @@ fn analyze(cfg: &ConfigFile) -> MultiMap<CheckOptions, CheckOptions> {
             Ok(m) => m,
             Err(e) => {
                 warn!("Can't get metadata for file {}. {}", &file_path, e);
+                stats.skip("unreadable");
                 continue;
             }
         };
@@ -110,7 +175,11 @@ fn analyze(cfg: &ConfigFile) -> MultiMap<CheckOptions, CheckOptions> {
         file_opt.name = Some(file_path.clone());
 
         file_opt.size = Some(file_metadata.len());
-        let file_size = if cfg.size { file_opt.size } else { None };
+        let file_size = if cfg.size || cfg.content {
+            file_opt.size
+        } else {
+            None
+        };
 
         file_opt.created = file_metadata.created().ok();
         if cfg.date_created {
@@ -122,62 +191,162 @@ fn analyze(cfg: &ConfigFile) -> MultiMap<CheckOptions, CheckOptions> {
             file_date_m = file_opt.modified;
         };
 
-        if (cfg.min_size > 0 && file_metadata.len() < cfg.min_size)
-            || (cfg.max_size > 0 && file_metadata.len() > cfg.max_size)
-            || (min_create_date.is_some()
-                && file_date_c.is_some()
-                && file_date_c.unwrap() > min_create_date.unwrap())
-            || (max_create_date.is_some()
-                && file_date_c.is_some()
-                && file_date_c.unwrap() < max_create_date.unwrap())
-            || (min_mod_date.is_some()
-                && file_date_m.is_some()
-                && file_date_m.unwrap() > min_mod_date.unwrap())
-            || (max_mod_date.is_some()
-                && file_date_m.is_some()
-                && file_date_m.unwrap() > max_mod_date.unwrap())
-            || (re.is_some() && !re.as_ref().unwrap().is_match(&file_name.as_str()))
+        stats.files_scanned += 1;
+        stats.bytes_scanned += file_metadata.len();
+
+        let skipped_by = if cfg.min_size > 0 && file_metadata.len() < cfg.min_size {
+            Some("min_size")
+        } else if cfg.max_size > 0 && file_metadata.len() > cfg.max_size {
+            Some("max_size")
+        } else if min_create_date.is_some()
+            && file_date_c.is_some()
+            && file_date_c.unwrap() > min_create_date.unwrap()
+        {
+            Some("min_createdate")
+        } else if max_create_date.is_some()
+            && file_date_c.is_some()
+            && file_date_c.unwrap() < max_create_date.unwrap()
+        {
+            Some("max_createdate")
+        } else if min_mod_date.is_some()
+            && file_date_m.is_some()
+            && file_date_m.unwrap() > min_mod_date.unwrap()
+        {
+            Some("min_moddate")
+        } else if max_mod_date.is_some()
+            && file_date_m.is_some()
+            && file_date_m.unwrap() > max_mod_date.unwrap()
         {
+            Some("max_moddate")
+        } else if re.is_some() && !re.as_ref().unwrap().is_match(file_name.as_str()) {
+            Some("name_filter")
+        } else {
+            None
+        };
+
+        if let Some(filter) = skipped_by {
+            stats.skip(filter);
             continue;
         }
 
-        if cfg.hash_md5 || cfg.hash_sha512 {
-            let mut file = match File::open(file_path.clone()) {
-                Ok(md5) => md5,
+        let file_key = check::CheckOptions {
+            name: if cfg.name { Some(file_name) } else { None },
+            size: file_size,
+            created: file_date_c,
+            modified: file_date_m,
+            digests: Digests::new(),
+            content: None,
+        };
+
+        on_file(file_key, file_opt);
+    }
+}
+
+fn analyze(cfg: &ConfigFile) -> (MultiMap<CheckOptions, CheckOptions>, ScanStats) {
+    let mut files: MultiMap<check::CheckOptions, CheckOptions> = MultiMap::new();
+    let mut stats = ScanStats::default();
+    let mut candidates: Vec<(CheckOptions, CheckOptions)> = Vec::new();
+
+    let need_hash = !cfg.hash_algos.is_empty();
+
+    if need_hash {
+        let mut cache = HashCache::load(cfg);
+
+        // Stages 1 and 2 overlap with the walk: files with unique size can't have doubles
+        // by content, so a file is hashed by head and tail as soon as its size isn't unique
+        let mut by_size: HashMap<(CheckOptions, Option<u64>), Vec<usize>> = HashMap::new();
+        let mut edges = stream_parallel(
+            cfg.jobs,
+            |send| {
+                walk(cfg, &mut stats, |file_key, file_opt| {
+                    let idx = candidates.len();
+                    let same_size = by_size
+                        .entry((file_key.clone(), file_opt.size))
+                        .or_default();
+                    same_size.push(idx);
+                    candidates.push((file_key, file_opt));
+
+                    let ready = match same_size.len() {
+                        1 => &same_size[..0],
+                        2 => &same_size[..],
+                        n => &same_size[n - 1..],
+                    };
+                    for &ready_idx in ready {
+                        let path = candidates[ready_idx].1.name.clone().unwrap_or_default();
+                        send((ready_idx, path));
+                    }
+                });
+            },
+            |(_, path)| cache.file_edges(path, PARTIAL_HASH_SIZE, cfg.partial_hash_algo),
+        );
+        edges.sort_by_key(|((idx, _), _)| *idx);
+
+        let mut edge_hashed: Vec<(CheckOptions, CheckOptions, String)> = Vec::new();
+        for ((idx, file_path), res) in edges {
+            match res {
+                Ok((stamp, h)) => {
+                    cache.store_edges(&file_path, &stamp, cfg.partial_hash_algo, &h);
+                    let (file_key, file_opt) = candidates[idx].clone();
+                    edge_hashed.push((file_key, file_opt, h));
+                }
                 Err(e) => {
-                    warn!("Can't open file {}. {}", file_path, e);
+                    warn!("Can't read file {}. {}", file_path, e);
+                    stats.skip("unreadable");
+                }
+            }
+        }
+
+        candidates = retain_doubles(edge_hashed, |(key, file, edges)| {
+            (key.clone(), file.size, edges.clone())
+        })
+        .into_iter()
+        .map(|(file_key, file_opt, _)| (file_key, file_opt))
+        .collect();
+
+        // Stage 3: full hash of surviving candidates
+        let hashes = map_parallel(cfg.jobs, &candidates, |(_, file_opt)| {
+            cache.file_hashes(
+                file_opt.name.as_deref().unwrap_or_default(),
+                &cfg.hash_algos,
+            )
+        });
+
+        for ((mut file_key, file_opt), res) in candidates.into_iter().zip(hashes) {
+            let file_path = file_opt.name.clone().unwrap_or_default();
+            let hashes = match res {
+                Ok((stamp, h)) => {
+                    cache.store_hashes(&file_path, &stamp, &h);
+                    h
+                }
+                Err(e) => {
+                    warn!("Can't read file {}. {}", file_path, e);
+                    stats.skip("unreadable");
                     continue;
                 }
             };
-            let mut contents = Vec::<u8>::new();
-            if file.read_to_end(&mut contents).is_err() {
-                warn!("Can't read file {}", file_path);
-                continue;
-            }
 
-            if cfg.hash_md5 {
-                let md5_digest = md5::compute(&contents.as_slice());
-                file_md5 = Some(format!("{:x}", md5_digest));
-            }
+            file_key.digests = hashes;
+            files.insert(file_key, file_opt);
+        }
 
-            if cfg.hash_sha512 {
-                let mut hasher = Sha512::new();
-                hasher.update(&contents.as_slice());
-                let sha512_digest = hasher.finalize();
-                file_sha512 = Some(format!("{:x}", sha512_digest));
-            }
+        cache.save();
+    } else {
+        walk(cfg, &mut stats, |file_key, file_opt| {
+            candidates.push((file_key, file_opt))
+        });
+
+        // Files with unique size can't have doubles by content
+        if cfg.content {
+            candidates = retain_doubles(candidates, |(key, file)| (key.clone(), file.size));
         }
 
-        let file_key = check::CheckOptions {
-            name: Some(file_name),
-            size: file_size,
-            created: file_date_c,
-            modified: file_date_m,
-            md5: file_md5,
-            sha512: file_sha512,
-        };
+        for (file_key, file_opt) in candidates {
+            files.insert(file_key, file_opt);
+        }
+    }
 
-        files.insert(file_key, file_opt);
+    if cfg.content {
+        files = split_by_content(cfg.jobs, files);
     }
 
     if cfg.debug {
@@ -187,6 +356,11 @@ fn analyze(cfg: &ConfigFile) -> MultiMap<CheckOptions, CheckOptions> {
     let vals = files
         .iter_all()
         .filter(|(_, v)| v.len() > 1)
+        .filter(|(_, v)| {
+            cfg.reference_paths.is_empty()
+                || (v.iter().any(|f| is_reference(cfg, f))
+                    && v.iter().any(|f| !is_reference(cfg, f)))
+        })
         .map(|(k, v)| {
             (
                 k.clone(),
@@ -196,16 +370,108 @@ fn analyze(cfg: &ConfigFile) -> MultiMap<CheckOptions, CheckOptions> {
                     .collect(),
             )
         })
-        .sorted_by(|(k0, _), (k1, _)| compare(cfg, k0, k1));
+        .sorted_by(|(k0, _), (k1, _)| compare(cfg, k0, k1).then_with(|| k0.cmp(k1)));
 
     if cfg.first_n > 0 {
-        return MultiMap::from_iter(
-            vals.take(cfg.first_n)
-                .collect::<Vec<(CheckOptions, Vec<CheckOptions>)>>(),
+        return (
+            MultiMap::from_iter(
+                vals.take(cfg.first_n)
+                    .collect::<Vec<(CheckOptions, Vec<CheckOptions>)>>(),
+            ),
+            stats,
         );
     }
 
-    MultiMap::from_iter(vals.collect::<Vec<(CheckOptions, Vec<CheckOptions>)>>())
+    (
+        MultiMap::from_iter(vals.collect::<Vec<(CheckOptions, Vec<CheckOptions>)>>()),
+        stats,
+    )
+}
+
+fn retain_doubles<T, K: Hash + Eq>(items: Vec<T>, group_key: impl Fn(&T) -> K) -> Vec<T> {
+    let mut counts: HashMap<K, usize> = HashMap::new();
+    for item in items.iter() {
+        *counts.entry(group_key(item)).or_insert(0) += 1;
+    }
+
+    items
+        .into_iter()
+        .filter(|item| counts[&group_key(item)] > 1)
+        .collect()
+}
+
+// Doubles of a group by content, files which can't be read are dropped
+fn content_classes(group: &[CheckOptions]) -> Vec<Vec<CheckOptions>> {
+    let mut classes: Vec<Vec<CheckOptions>> = Vec::new();
+
+    'files: for file in group {
+        let file_path = file.name.clone().unwrap_or_default();
+
+        for class in classes.iter_mut() {
+            let class_path = class[0].name.clone().unwrap_or_default();
+            match compare_content(&class_path, &file_path) {
+                Ok(true) => {
+                    class.push(file.clone());
+                    continue 'files;
+                }
+                Ok(false) => {}
+                Err(e) => {
+                    warn!("Can't compare {} with {}. {}", class_path, file_path, e);
+                    continue 'files;
+                }
+            }
+        }
+
+        classes.push(vec![file.clone()]);
+    }
+
+    classes
+}
+
+// Groups are compared on `jobs` threads, one group at a time on each
+fn split_by_content(
+    jobs: usize,
+    files: MultiMap<CheckOptions, CheckOptions>,
+) -> MultiMap<CheckOptions, CheckOptions> {
+    let mut res: MultiMap<CheckOptions, CheckOptions> = MultiMap::new();
+
+    let groups: Vec<(CheckOptions, Vec<CheckOptions>)> = files.into_iter().collect();
+    let classes = map_parallel(jobs, &groups, |(_, group)| {
+        if group.len() < 2 {
+            Vec::new()
+        } else {
+            content_classes(group)
+        }
+    });
+
+    for ((key, group), classes) in groups.into_iter().zip(classes) {
+        if group.len() < 2 {
+            res.insert_many(key, group);
+            continue;
+        }
+
+        for (idx, class) in classes.into_iter().enumerate() {
+            let mut class_key = key.clone();
+            class_key.content = Some(idx);
+            res.insert_many(class_key, class);
+        }
+    }
+
+    res
+}
+
+// Criteria not used for grouping are missing in the key, take them from the first file
+fn group_sort_key(key: &CheckOptions, files: &[CheckOptions]) -> CheckOptions {
+    let mut sort_key = key.clone();
+
+    if let Some(first) = files.first() {
+        sort_key.name = sort_key.name.or_else(|| first.name.clone());
+        sort_key.size = sort_key.size.or(first.size);
+        sort_key.created = sort_key.created.or(first.created);
+        sort_key.modified = sort_key.modified.or(first.modified);
+    }
+
+    sort_key
 }
 
 fn sort_results<'a>(
@@ -213,6 +479,7 @@ fn sort_results<'a>(
     results: &'a MultiMap<CheckOptions, CheckOptions>,
 ) -> Vec<(&'a CheckOptions, &'a Vec<CheckOptions>)> {
     let mut res_vec: Vec<(&CheckOptions, &Vec<CheckOptions>)> = results.iter_all().collect();
+    res_vec.sort_by_key(|(key, _)| *key);
 
     if cfg.sort_res_name_asc
         || cfg.sort_res_name_desc
@@ -223,7 +490,10 @@ fn sort_results<'a>(
         || cfg.sort_res_mdate_asc
         || cfg.sort_res_mdate_desc
     {
-        res_vec.sort_by(|(&ref opt1, _), (&ref opt2, _)| {
+        res_vec.sort_by(|(key1, files1), (key2, files2)| {
+            let opt1 = &group_sort_key(key1, files1);
+            let opt2 = &group_sort_key(key2, files2);
+
             if cfg.sort_res_name_asc {
                 return opt1
                     .name
@@ -236,7 +506,7 @@ fn sort_results<'a>(
                     .name
                     .as_ref()
                     .unwrap()
-                    .partial_cmp(&opt1.name.as_ref().unwrap())
+                    .partial_cmp(opt1.name.as_ref().unwrap())
                     .unwrap();
             } else if cfg.sort_res_size_asc {
                 return opt1.size.unwrap().partial_cmp(&opt2.size.unwrap()).unwrap();
@@ -275,19 +545,23 @@ fn sort_results<'a>(
     res_vec
 }
 
-fn show_results(res_vec: &Vec<(&CheckOptions, &Vec<CheckOptions>)>) {
-    for (opt, pathes) in res_vec {
-        println!("{}", opt);
-        for path in pathes.iter() {
-            println!("    {}", path.name.clone().unwrap_or_default());
-        }
-        println!("");
-    }
+fn show_results(
+    cfg: &ConfigFile,
+    policies: &[KeepPolicy],
+    res_vec: &[(&CheckOptions, &Vec<CheckOptions>)],
+    summary: &Summary,
+) {
+    print!("{}", render(cfg, policies, res_vec, summary));
 }
 
-fn print_results(cfg: &ConfigFile, res_vec: &Vec<(&CheckOptions, &Vec<CheckOptions>)>) {
+fn print_results(
+    cfg: &ConfigFile,
+    policies: &[KeepPolicy],
+    res_vec: &[(&CheckOptions, &Vec<CheckOptions>)],
+    summary: &Summary,
+) {
     if cfg.out_filename.is_none() {
-        return ();
+        return;
     }
 
     let mut output = match File::create(cfg.out_filename.as_ref().unwrap()) {
@@ -301,68 +575,109 @@ fn print_results(cfg: &ConfigFile, res_vec: &Vec<(&CheckOptions, &Vec<CheckOptio
         }
     };
 
-    for (opt, pathes) in res_vec {
-        match writeln!(&mut output, "{}", opt) {
-            Ok(_) => {}
-            Err(e) => {
-                panic!(
-                    "Can't write to file {}! {}",
-                    cfg.out_filename.as_ref().unwrap().display(),
-                    e
-                )
-            }
-        }
-
-        for path in pathes.iter() {
-            match writeln!(&mut output, "    {}", path.name.clone().unwrap_or_default()) {
-                Ok(_) => {}
-                Err(e) => {
-                    panic!(
-                        "Can't write to file {}! {}",
-                        cfg.out_filename.as_ref().unwrap().display(),
-                        e
-                    )
-                }
-            }
-        }
-        match writeln!(&mut output, "") {
-            Ok(_) => {}
-            Err(e) => {
-                panic!(
-                    "Can't write to file {}! {}",
-                    cfg.out_filename.as_ref().unwrap().display(),
-                    e
-                )
-            }
+    match output.write_all(render(cfg, policies, res_vec, summary).as_bytes()) {
+        Ok(_) => {}
+        Err(e) => {
+            panic!(
+                "Can't write to file {}! {}",
+                cfg.out_filename.as_ref().unwrap().display(),
+                e
+            )
         }
     }
 }
 
-fn delete_results(cfg: &ConfigFile, results: &MultiMap<CheckOptions, CheckOptions>) {
+fn delete_results(
+    cfg: &ConfigFile,
+    policies: &[KeepPolicy],
+    results: &MultiMap<CheckOptions, CheckOptions>,
+) {
     if !cfg.delete {
         return;
     }
 
-    for (template, files) in results.iter_all() {
+    let action = Action::from_config(cfg);
+    let mut journal = Journal::open(cfg);
+    let mut reclaimed: u64 = 0;
+
+    // Same order as shown results, so prompts are reproducible
+    let res_vec = sort_results(cfg, results);
+
+    let marks = if cfg.tui && !cfg.force_delete {
+        match tui::review(cfg, policies, &action, &res_vec) {
+            Some(m) => Some(m),
+            None => {
+                println!("Nothing changed");
+                return;
+            }
+        }
+    } else if cfg.review && !cfg.force_delete {
+        Some(review(cfg, policies, &action, &res_vec))
+    } else {
+        None
+    };
+
+    let (would, reclaimed_msg) = if cfg.dry_run {
+        ("would ", "would be reclaimed")
+    } else {
+        ("", "reclaimed")
+    };
+
+    // Set when user asks to stop prompting and use keep policies for remaining groups
+    let mut same_rule = false;
+
+    // Skipped groups are asked again after the others, without another skip
+    let mut queue: VecDeque<_> = res_vec.into_iter().map(|(t, f)| (t, f, true)).collect();
+
+    while let Some((template, files, can_skip)) = queue.pop_front() {
         info!("{}", template);
         if !cfg.silent_mode || !cfg.force_delete {
             println!("{}", template);
         }
 
-        let mut num_del: usize = 0;
-        let mut idx_file: usize = 0;
-        let max_del = files.len() - 1;
+        let reference_mode = !cfg.reference_paths.is_empty();
+        let mut kept = match &marks {
+            Some(m) => files
+                .iter()
+                .find(|f| f.name.as_deref().and_then(|n| m.get(n)) == Some(&Mark::Keep)),
+            None => select_kept(cfg, policies, files),
+        };
 
-        for file in files.iter() {
-            idx_file += 1;
+        if marks.is_none() && !cfg.force_delete && !same_rule {
+            let default = kept.and_then(|k| files.iter().position(|f| f == k));
+            match choose_kept(cfg, &action, files, default, can_skip) {
+                Choice::Keep(idx) => kept = files.get(idx),
+                Choice::SkipGroup => {
+                    info!("    skipped for now!");
+                    queue.push_back((template, files, false));
+                    continue;
+                }
+                Choice::KeepAll => {
+                    info!("    keep all!");
+                    continue;
+                }
+                Choice::SameRule => same_rule = true,
+            }
+        }
 
-            println!("    {}...   ", file);
+        let kept_path = kept.and_then(|k| k.name.as_deref());
+
+        let mut group_reclaimed: u64 = 0;
+
+        for file in files.iter() {
+            if is_reference(cfg, file) {
+                if !cfg.silent_mode {
+                    println!("    {}...   keep (reference)!", file);
+                }
+                info!("    {} - keep (reference)!", file);
+                continue;
+            }
 
             if cfg.force_delete {
                 if !cfg.silent_mode {
                     print!("    {}...   ", file);
                 }
-                if idx_file == 1 {
+                if kept == Some(file) && !reference_mode {
                     if !cfg.silent_mode {
                         println!("keep!");
                     }
@@ -371,28 +686,56 @@ fn delete_results(cfg: &ConfigFile, results: &MultiMap<CheckOptions, CheckOption
                 }
             }
 
-            if !cfg.force_delete {
-                let prompt = format! {"    {} delete (y/n)?", file};
-
-                if num_del == max_del
-                    || !Confirm::new()
-                        .with_prompt(prompt)
-                        .default(true)
-                        .show_default(true)
-                        .interact()
-                        .unwrap()
-                {
-                    info!("    {} - keep!", file);
-                    continue;
+            let mut file_action = action.clone();
+
+            if let Some(marks) = &marks {
+                let mark = file
+                    .name
+                    .as_deref()
+                    .and_then(|n| marks.get(n))
+                    .copied()
+                    .unwrap_or(Mark::Keep);
+
+                match mark.action(&action) {
+                    Some(a) => file_action = a,
+                    None => {
+                        if !cfg.silent_mode {
+                            println!("    {}...   keep!", file);
+                        }
+                        info!("    {} - keep!", file);
+                        continue;
+                    }
                 }
+            } else if !cfg.force_delete && kept == Some(file) {
+                if !cfg.silent_mode {
+                    println!("    {}...   keep!", file);
+                }
+                info!("    {} - keep!", file);
+                continue;
             }
 
-            if cfg.force_delete {
-                if !cfg.silent_mode {
-                    println!("delete!");
+            // Kept file first, then any other copy still on disk
+            let other = kept
+                .into_iter()
+                .chain(files.iter())
+                .find(|f| *f != file && Path::new(f.name.as_deref().unwrap_or_default()).exists());
+
+            if let Err(e) = reverify(file, other) {
+                if cfg.force_delete && !cfg.silent_mode {
+                    println!("skipped - {}", e);
+                } else {
+                    println!("    {} skipped - {}", file, e);
                 }
+                warn!("    {} skipped - {}", file, e);
+                continue;
+            }
+
+            if cfg.force_delete && !cfg.silent_mode {
+                println!("{}{}!", would, file_action);
+            } else if !cfg.silent_mode {
+                println!("    {}...   {}{}!", file, would, file_action);
             }
-            info!("    {} - delete!", file);
+            info!("    {} - {}{}!", file, would, file_action);
 
             let path_to_del = match &file.name {
                 Some(s) => s,
@@ -403,24 +746,60 @@ fn delete_results(cfg: &ConfigFile, results: &MultiMap<CheckOptions, CheckOption
                 }
             };
 
-            if !cfg.debug {
-                match std::fs::remove_file(path_to_del) {
-                    Ok(_) => {
-                        num_del += 1;
+            if cfg.dry_run {
+                group_reclaimed += file.size.unwrap_or_default();
+            } else {
+                let original = absolute_path(Path::new(path_to_del))
+                    .map(|p| p.to_string_lossy().to_string())
+                    .unwrap_or_else(|_| path_to_del.clone());
+                let kept_abs = kept_path.map(|k| {
+                    absolute_path(Path::new(k))
+                        .map(|p| p.to_string_lossy().to_string())
+                        .unwrap_or_else(|_| k.to_string())
+                });
+
+                match file_action.apply(path_to_del, kept_path) {
+                    Ok(applied) => {
+                        group_reclaimed += applied.reclaimed;
+
+                        let mut entry = JournalEntry::new(
+                            &file_action.to_string(),
+                            original,
+                            kept_abs,
+                            &template.digests,
+                        );
+                        entry.size = applied.reclaimed;
+                        entry.moved_to = applied.moved_to.map(|p| p.to_string_lossy().to_string());
+                        journal.record(&entry);
                     }
                     Err(e) => {
-                        println!("Can't delete {} - {}", path_to_del, e);
-                        warn!("Can't delete {} - {}", path_to_del, e);
+                        println!("Can't {} {} - {}", file_action, path_to_del, e);
+                        warn!("Can't {} {} - {}", file_action, path_to_del, e);
                     }
                 }
             }
         }
+
+        reclaimed += group_reclaimed;
+        info!("    {} bytes {}", group_reclaimed, reclaimed_msg);
+        if !cfg.silent_mode {
+            println!("    {} bytes {}", group_reclaimed, reclaimed_msg);
+        }
+    }
+
+    info!("Total {} bytes {}", reclaimed, reclaimed_msg);
+    if !cfg.silent_mode {
+        println!("Total {} bytes {}", reclaimed, reclaimed_msg);
     }
 }
 
 fn main() -> Result<(), String> {
     //let start = Instant::now();
     let cfg = init::init().expect("Config file error");
+    let policies = match parse_policies(&cfg.keep_policies) {
+        Ok(p) => p,
+        Err(e) => panic!("{}", e),
+    };
 
     if init_log(&cfg.log_filename).is_err() {
         panic!("Can't init log file {}", &cfg.log_filename);
@@ -435,30 +814,42 @@ fn main() -> Result<(), String> {
         std::process::exit(0);
     }
 
+    if let Some(journal) = &cfg.restore_journal {
+        restore::restore(&cfg, journal);
+        return Ok(());
+    }
+
+    if let Some(results) = &cfg.apply_results {
+        let file_doubles = apply::load_results(&cfg, results);
+        delete_results(&cfg, &policies, &file_doubles);
+        return Ok(());
+    }
+
     if !cfg.name
         && !cfg.size
         && !cfg.date_created
         && !cfg.date_modified
-        && !cfg.hash_md5
-        && !cfg.hash_sha512
+        && cfg.hash_algos.is_empty()
+        && !cfg.content
     {
         error!("Need to turn on at least one file equality criteria");
         panic!("Need to turn on at least one file equality criteria")
     }
 
-    let file_doubles = analyze(&cfg);
+    let (file_doubles, scan_stats) = analyze(&cfg);
     let file_results = sort_results(&cfg, &file_doubles);
+    let summary = Summary::new(&cfg, &policies, scan_stats, &file_results);
 
     if !cfg.silent_mode {
-        show_results(&file_results);
+        show_results(&cfg, &policies, &file_results, &summary);
     }
 
     if cfg.out_filename.is_some() {
-        print_results(&cfg, &file_results);
+        print_results(&cfg, &policies, &file_results, &summary);
     }
 
     if cfg.delete {
-        delete_results(&cfg, &file_doubles);
+        delete_results(&cfg, &policies, &file_doubles);
     }
 
     Ok(())
@@ -0,0 +1,110 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Mutex};
+use std::thread;
+
+/// Number of worker threads, 0 means all available CPUs
+pub fn jobs_count(jobs: usize) -> usize {
+    match jobs {
+        0 => thread::available_parallelism().map_or(1, |n| n.get()),
+        n => n,
+    }
+}
+
+/// Maps items on a pool of `jobs` threads, results keep the order of items
+pub fn map_parallel<T, R, F>(jobs: usize, items: &[T], f: F) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(&T) -> R + Sync,
+{
+    let jobs = std::cmp::min(jobs_count(jobs), items.len());
+    if jobs <= 1 {
+        return items.iter().map(f).collect();
+    }
+
+    let next = AtomicUsize::new(0);
+    let results: Mutex<Vec<(usize, R)>> = Mutex::new(Vec::with_capacity(items.len()));
+
+    thread::scope(|scope| {
+        for _ in 0..jobs {
+            scope.spawn(|| loop {
+                let idx = next.fetch_add(1, Ordering::Relaxed);
+                if idx >= items.len() {
+                    break;
+                }
+
+                let res = f(&items[idx]);
+                results.lock().unwrap().push((idx, res));
+            });
+        }
+    });
+
+    let mut results = results.into_inner().unwrap();
+    results.sort_by_key(|(idx, _)| *idx);
+    results.into_iter().map(|(_, res)| res).collect()
+}
+
+/// Runs `f` on a pool of `jobs` threads for items which `produce` sends while it's
+/// still running, results keep the order items were sent in
+pub fn stream_parallel<T, R, P, F>(jobs: usize, produce: P, f: F) -> Vec<(T, R)>
+where
+    T: Send,
+    R: Send,
+    P: FnOnce(&mut dyn FnMut(T)),
+    F: Fn(&T) -> R + Sync,
+{
+    let (sender, receiver) = mpsc::channel::<(usize, T)>();
+    let receiver = Mutex::new(receiver);
+    let results: Mutex<Vec<(usize, T, R)>> = Mutex::new(Vec::new());
+
+    thread::scope(|scope| {
+        for _ in 0..jobs_count(jobs) {
+            scope.spawn(|| loop {
+                // Lock is released before the item is processed
+                let received = receiver.lock().unwrap().recv();
+                let Ok((idx, item)) = received else {
+                    break;
+                };
+
+                let res = f(&item);
+                results.lock().unwrap().push((idx, item, res));
+            });
+        }
+
+        let mut next: usize = 0;
+        produce(&mut |item| {
+            let _ = sender.send((next, item));
+            next += 1;
+        });
+
+        // Workers stop when all sent items are taken
+        drop(sender);
+    });
+
+    let mut results = results.into_inner().unwrap();
+    results.sort_by_key(|(idx, _, _)| *idx);
+    results
+        .into_iter()
+        .map(|(_, item, res)| (item, res))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stream_parallel_keeps_send_order() {
+        let res = stream_parallel(
+            4,
+            |send| {
+                for i in 0..100 {
+                    send(i);
+                }
+            },
+            |i| i * 2,
+        );
+
+        assert_eq!(res, (0..100).map(|i| (i, i * 2)).collect::<Vec<_>>());
+    }
+}
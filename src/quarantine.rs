@@ -0,0 +1,155 @@
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Component, Path, PathBuf};
+
+use chrono::Utc;
+use serde_derive::{Deserialize, Serialize};
+
+use crate::actions::absolute_path;
+use crate::hash::Digests;
+use crate::journal::{read_json_lines, JournalEntry};
+
+pub const MANIFEST_NAME: &str = "manifest.jsonl";
+
+/// One moved file in quarantine manifest
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    pub original: String,
+    pub moved_to: String,
+    pub kept: Option<String>,
+    pub size: u64,
+    pub time: String,
+}
+
+// Quarantine path mirrors absolute original path without root and drive prefix
+fn mirror_path(dir: &Path, original: &Path) -> PathBuf {
+    let mut res = dir.to_path_buf();
+    for component in original.components() {
+        if let Component::Normal(c) = component {
+            res.push(c);
+        }
+    }
+
+    if !res.exists() {
+        return res;
+    }
+
+    let mut idx: usize = 1;
+    loop {
+        let mut name = res.clone().into_os_string();
+        name.push(format!(".{}", idx));
+        let candidate = PathBuf::from(name);
+        if !candidate.exists() {
+            return candidate;
+        }
+        idx += 1;
+    }
+}
+
+/// Renames file, copies and removes it if rename fails (e.g. across file systems)
+pub fn move_file(from: &Path, to: &Path) -> io::Result<()> {
+    if let Some(parent) = to.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    if fs::rename(from, to).is_ok() {
+        return Ok(());
+    }
+
+    fs::copy(from, to)?;
+    if let Err(e) = fs::remove_file(from) {
+        let _ = fs::remove_file(to);
+        return Err(e);
+    }
+
+    Ok(())
+}
+
+impl From<ManifestEntry> for JournalEntry {
+    fn from(entry: ManifestEntry) -> Self {
+        Self {
+            action: String::from("move"),
+            original: entry.original,
+            kept: entry.kept,
+            moved_to: Some(entry.moved_to),
+            hash: Digests::new(),
+            size: entry.size,
+            time: entry.time,
+        }
+    }
+}
+
+/// Reads moves from manifest of quarantine directory as journal entries
+pub fn read_manifest(dir: &Path) -> Result<Vec<JournalEntry>, String> {
+    let entries: Vec<ManifestEntry> = read_json_lines(&dir.join(MANIFEST_NAME))?;
+    Ok(entries.into_iter().map(JournalEntry::from).collect())
+}
+
+fn append_manifest(dir: &Path, entry: &ManifestEntry) -> io::Result<()> {
+    let mut manifest = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(dir.join(MANIFEST_NAME))?;
+
+    let line = serde_json::to_string(entry).map_err(io::Error::other)?;
+    writeln!(manifest, "{}", line)
+}
+
+/// Moves victim into quarantine `dir`, creating it if needed, and records it in manifest.
+/// Returns size of moved file and its new path.
+pub fn quarantine(victim: &Path, kept: Option<&Path>, dir: &Path) -> io::Result<(u64, PathBuf)> {
+    fs::create_dir_all(dir)?;
+    let original = absolute_path(victim)?;
+
+    let size = fs::symlink_metadata(victim)?.len();
+    let moved_to = mirror_path(dir, &original);
+
+    move_file(victim, &moved_to)?;
+
+    let entry = ManifestEntry {
+        original: original.to_string_lossy().to_string(),
+        moved_to: moved_to.to_string_lossy().to_string(),
+        kept: kept.map(|k| {
+            k.canonicalize()
+                .unwrap_or_else(|_| k.to_path_buf())
+                .to_string_lossy()
+                .to_string()
+        }),
+        size,
+        time: Utc::now().to_rfc3339(),
+    };
+
+    if let Err(e) = append_manifest(dir, &entry) {
+        warn!(
+            "Can't write quarantine manifest in {} - {}",
+            dir.display(),
+            e
+        );
+    }
+
+    Ok((size, moved_to))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mirror_path_adds_suffix_on_collision() {
+        let dir = std::env::temp_dir().join(format!("fundoubler-mirror-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let original = Path::new("/data/photos/a.jpg");
+        let mirrored = dir.join("data/photos/a.jpg");
+
+        assert_eq!(mirror_path(&dir, original), mirrored);
+
+        fs::create_dir_all(mirrored.parent().unwrap()).unwrap();
+        fs::write(&mirrored, "first").unwrap();
+        assert_eq!(mirror_path(&dir, original), dir.join("data/photos/a.jpg.1"));
+
+        fs::write(dir.join("data/photos/a.jpg.1"), "second").unwrap();
+        assert_eq!(mirror_path(&dir, original), dir.join("data/photos/a.jpg.2"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,102 @@
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::path::Path;
+
+/// Shares extents of `victim` with `kept`. FIDEDUPERANGE is used instead of FICLONE
+/// because kernel compares ranges itself and refuses to share differing data.
+/// Returns number of deduplicated bytes.
+pub fn reflink(victim: &Path, kept: &Path) -> io::Result<u64> {
+    let src = File::open(kept)?;
+    let dest = OpenOptions::new().read(true).write(true).open(victim)?;
+
+    let len = src.metadata()?.len();
+    if dest.metadata()?.len() != len {
+        return Err(io::Error::other("files have different sizes"));
+    }
+
+    dedupe_range(&src, &dest, len)
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    // _IOWR(0x94, 54, struct file_dedupe_range)
+    pub const FIDEDUPERANGE: u64 = 0xC018_9436;
+
+    pub const FILE_DEDUPE_RANGE_DIFFERS: i32 = 1;
+
+    // Some file systems limit length of one request
+    pub const MAX_DEDUPE_CHUNK: u64 = 16 * 1024 * 1024;
+
+    #[repr(C)]
+    pub struct FileDedupeRangeInfo {
+        pub dest_fd: i64,
+        pub dest_offset: u64,
+        pub bytes_deduped: u64,
+        pub status: i32,
+        pub reserved: u32,
+    }
+
+    #[repr(C)]
+    pub struct FileDedupeRange {
+        pub src_offset: u64,
+        pub src_length: u64,
+        pub dest_count: u16,
+        pub reserved1: u16,
+        pub reserved2: u32,
+        pub info: [FileDedupeRangeInfo; 1],
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn dedupe_range(src: &File, dest: &File, len: u64) -> io::Result<u64> {
+    use std::os::unix::io::AsRawFd;
+
+    use linux::*;
+
+    let mut offset: u64 = 0;
+    while offset < len {
+        let mut range = FileDedupeRange {
+            src_offset: offset,
+            src_length: std::cmp::min(len - offset, MAX_DEDUPE_CHUNK),
+            dest_count: 1,
+            reserved1: 0,
+            reserved2: 0,
+            info: [FileDedupeRangeInfo {
+                dest_fd: dest.as_raw_fd() as i64,
+                dest_offset: offset,
+                bytes_deduped: 0,
+                status: 0,
+                reserved: 0,
+            }],
+        };
+
+        // SAFETY: range is a valid file_dedupe_range with one info record
+        let res = unsafe { libc::ioctl(src.as_raw_fd(), FIDEDUPERANGE as _, &mut range) };
+        if res < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let info = &range.info[0];
+        if info.status < 0 {
+            return Err(io::Error::from_raw_os_error(-info.status));
+        }
+        if info.status == FILE_DEDUPE_RANGE_DIFFERS {
+            return Err(io::Error::other("file contents differ"));
+        }
+        if info.bytes_deduped == 0 {
+            return Err(io::Error::other("file system deduplicated nothing"));
+        }
+
+        offset += info.bytes_deduped;
+    }
+
+    Ok(offset)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn dedupe_range(_src: &File, _dest: &File, _len: u64) -> io::Result<u64> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "reflinks are supported only on Linux",
+    ))
+}
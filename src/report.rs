@@ -0,0 +1,526 @@
+use std::cmp::Reverse;
+use std::fmt;
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::SystemTime;
+
+use chrono::offset::Utc;
+use chrono::DateTime;
+use serde_derive::{Deserialize, Serialize};
+
+use crate::actions::absolute_path;
+use crate::check::{is_reference, CheckOptions};
+use crate::hash::{Digests, HashAlgorithm};
+use crate::init::ConfigFile;
+use crate::keep::{select_kept, KeepPolicy};
+use crate::stats::Summary;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    #[default]
+    Text,
+    Json,
+    Csv,
+    Tsv,
+    Html,
+}
+
+impl fmt::Display for OutputFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OutputFormat::Text => write!(f, "text"),
+            OutputFormat::Json => write!(f, "json"),
+            OutputFormat::Csv => write!(f, "csv"),
+            OutputFormat::Tsv => write!(f, "tsv"),
+            OutputFormat::Html => write!(f, "html"),
+        }
+    }
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "text" => Ok(OutputFormat::Text),
+            "json" => Ok(OutputFormat::Json),
+            "csv" => Ok(OutputFormat::Csv),
+            "tsv" => Ok(OutputFormat::Tsv),
+            "html" => Ok(OutputFormat::Html),
+            _ => Err(format!("Unknown output format {}", s)),
+        }
+    }
+}
+
+/// Size of all copies in group except one
+pub fn wasted_bytes(files: &[CheckOptions]) -> u64 {
+    let sizes = files.iter().map(|f| f.size.unwrap_or_default());
+    sizes.clone().sum::<u64>() - sizes.max().unwrap_or_default()
+}
+
+fn format_time(time: Option<SystemTime>) -> Option<String> {
+    time.map(|t| DateTime::<Utc>::from(t).to_rfc3339())
+}
+
+fn parse_time(time: &Option<String>) -> Result<Option<SystemTime>, String> {
+    time.as_deref()
+        .map(|t| {
+            DateTime::parse_from_rfc3339(t)
+                .map(SystemTime::from)
+                .map_err(|e| format!("Can't parse time {} - {}", t, e))
+        })
+        .transpose()
+}
+
+#[derive(Serialize, Deserialize)]
+struct JsonFile {
+    path: String,
+    size: Option<u64>,
+    created: Option<String>,
+    modified: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct JsonGroup {
+    name: Option<String>,
+    size: Option<u64>,
+    created: Option<String>,
+    modified: Option<String>,
+    digests: Digests,
+    wasted_bytes: u64,
+    files: Vec<JsonFile>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct JsonReport {
+    #[serde(skip_deserializing)]
+    summary: Summary,
+    groups: Vec<JsonGroup>,
+}
+
+fn render_text(res_vec: &[(&CheckOptions, &Vec<CheckOptions>)], summary: &Summary) -> String {
+    let mut res = String::new();
+
+    for (opt, pathes) in res_vec {
+        let _ = writeln!(res, "{}", opt);
+        for path in pathes.iter() {
+            let _ = writeln!(res, "    {}", path.name.clone().unwrap_or_default());
+        }
+        let _ = writeln!(res);
+    }
+
+    let _ = write!(res, "{}", summary);
+
+    res
+}
+
+// Absolute path, so results can be applied from any directory
+fn json_path(path: &str) -> String {
+    absolute_path(Path::new(path))
+        .map(|p| p.to_string_lossy().to_string())
+        .unwrap_or_else(|_| path.to_string())
+}
+
+fn render_json(res_vec: &[(&CheckOptions, &Vec<CheckOptions>)], summary: &Summary) -> String {
+    let report = JsonReport {
+        summary: summary.clone(),
+        groups: res_vec
+            .iter()
+            .map(|(opt, files)| JsonGroup {
+                name: opt.name.clone(),
+                size: opt.size,
+                created: format_time(opt.created),
+                modified: format_time(opt.modified),
+                digests: opt.digests.clone(),
+                wasted_bytes: wasted_bytes(files),
+                files: files
+                    .iter()
+                    .map(|f| JsonFile {
+                        path: json_path(f.name.as_deref().unwrap_or_default()),
+                        size: f.size,
+                        created: format_time(f.created),
+                        modified: format_time(f.modified),
+                    })
+                    .collect(),
+            })
+            .collect(),
+    };
+
+    match serde_json::to_string_pretty(&report) {
+        Ok(s) => s + "\n",
+        Err(e) => panic!("Can't serialize results to JSON! {}", e),
+    }
+}
+
+/// Reads groups back from results file written with JSON format
+pub fn read_results(path: &Path) -> Result<Vec<(CheckOptions, Vec<CheckOptions>)>, String> {
+    let text = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    if !text.trim_start().starts_with('{') {
+        return Err(String::from(
+            "not a JSON results file, scan again with --format json",
+        ));
+    }
+    let report: JsonReport = serde_json::from_str(&text).map_err(|e| e.to_string())?;
+
+    report
+        .groups
+        .into_iter()
+        .enumerate()
+        .map(|(idx, group)| {
+            // Index keeps apart groups split by content which have equal keys
+            let key = CheckOptions {
+                name: group.name,
+                size: group.size,
+                created: parse_time(&group.created)?,
+                modified: parse_time(&group.modified)?,
+                digests: group.digests,
+                content: Some(idx),
+            };
+
+            let files = group
+                .files
+                .into_iter()
+                .map(|f| {
+                    Ok(CheckOptions {
+                        name: Some(f.path),
+                        size: f.size,
+                        created: parse_time(&f.created)?,
+                        modified: parse_time(&f.modified)?,
+                        digests: Digests::new(),
+                        content: None,
+                    })
+                })
+                .collect::<Result<Vec<CheckOptions>, String>>()?;
+
+            Ok((key, files))
+        })
+        .collect()
+}
+
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+fn tsv_field(field: &str) -> String {
+    field.replace(['\t', '\n', '\r'], " ")
+}
+
+// One row per file with recommendation what to do with it,
+// summary rows have group "summary", metric name in path and value in size
+fn render_table(
+    cfg: &ConfigFile,
+    policies: &[KeepPolicy],
+    res_vec: &[(&CheckOptions, &Vec<CheckOptions>)],
+    summary: &Summary,
+    separator: char,
+    field: fn(&str) -> String,
+) -> String {
+    let header = [
+        "group",
+        "path",
+        "size",
+        "modified",
+        "created",
+        "md5",
+        "sha512",
+        "recommendation",
+    ];
+    let mut res = header.join(&separator.to_string()) + "\n";
+
+    for (idx, (opt, files)) in res_vec.iter().enumerate() {
+        let kept = select_kept(cfg, policies, files);
+
+        for file in files.iter() {
+            let recommendation = if kept == Some(file) || is_reference(cfg, file) {
+                "keep"
+            } else {
+                "delete"
+            };
+
+            let row = [
+                (idx + 1).to_string(),
+                file.name.clone().unwrap_or_default(),
+                file.size.map(|s| s.to_string()).unwrap_or_default(),
+                format_time(file.modified).unwrap_or_default(),
+                format_time(file.created).unwrap_or_default(),
+                opt.digests
+                    .get(&HashAlgorithm::Md5)
+                    .cloned()
+                    .unwrap_or_default(),
+                opt.digests
+                    .get(&HashAlgorithm::Sha512)
+                    .cloned()
+                    .unwrap_or_default(),
+                recommendation.to_string(),
+            ];
+
+            res += &row
+                .iter()
+                .map(|f| field(f))
+                .collect::<Vec<String>>()
+                .join(&separator.to_string());
+            res.push('\n');
+        }
+    }
+
+    for (name, value) in summary.rows() {
+        let mut row = vec![String::from("summary"), name, value.to_string()];
+        row.resize(header.len(), String::new());
+
+        res += &row
+            .iter()
+            .map(|f| field(f))
+            .collect::<Vec<String>>()
+            .join(&separator.to_string());
+        res.push('\n');
+    }
+
+    res
+}
+
+pub fn human_size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["KiB", "MiB", "GiB", "TiB", "PiB"];
+
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+fn html_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+pub fn file_extension(path: &str) -> String {
+    Path::new(path)
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_default()
+}
+
+pub fn file_dir(path: &str) -> String {
+    Path::new(path)
+        .parent()
+        .map(|p| p.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
+
+const HTML_HEAD: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>fundoubler report</title>
+<style>
+body { font-family: sans-serif; margin: 2em; }
+summary { cursor: pointer; padding: 0.2em 0; }
+.wasted { color: #a00; font-weight: bold; }
+.meta { color: #666; }
+.filters input { margin-right: 1em; }
+ul { margin-top: 0.2em; }
+</style>
+</head>
+<body>
+<h1>fundoubler report</h1>
+"#;
+
+const HTML_SCRIPT: &str = r#"<script>
+function applyFilters() {
+    var ext = document.getElementById("ext").value.trim().toLowerCase().replace(/^\./, "");
+    var dir = document.getElementById("dir").value.trim();
+    var shown = 0;
+    document.querySelectorAll("details.group").forEach(function (group) {
+        var any = false;
+        group.querySelectorAll("li").forEach(function (file) {
+            var ok = (ext === "" || file.dataset.ext === ext)
+                && (dir === "" || file.dataset.dir.indexOf(dir) >= 0);
+            file.style.display = ok ? "" : "none";
+            any = any || ok;
+        });
+        group.style.display = any ? "" : "none";
+        if (any) { shown++; }
+    });
+    document.getElementById("shown").textContent = shown;
+}
+</script>
+</body>
+</html>
+"#;
+
+// Static page without external assets, groups with most wasted space go first
+fn render_html(res_vec: &[(&CheckOptions, &Vec<CheckOptions>)], summary: &Summary) -> String {
+    let mut groups: Vec<&(&CheckOptions, &Vec<CheckOptions>)> = res_vec.iter().collect();
+    groups.sort_by_key(|(_, files)| Reverse(wasted_bytes(files)));
+
+    let total_files: usize = groups.iter().map(|(_, files)| files.len()).sum();
+    let total_wasted: u64 = groups.iter().map(|(_, files)| wasted_bytes(files)).sum();
+
+    let mut res = String::from(HTML_HEAD);
+
+    let _ = writeln!(
+        res,
+        "<p>Groups: <b>{}</b> (shown: <b id=\"shown\">{}</b>), files: <b>{}</b>, wasted: <b class=\"wasted\">{}</b> ({} bytes)</p>",
+        groups.len(),
+        groups.len(),
+        total_files,
+        human_size(total_wasted),
+        total_wasted
+    );
+    let _ = writeln!(
+        res,
+        "<details class=\"summary\"><summary>Summary</summary>\n<table>"
+    );
+    for (name, value) in summary.rows() {
+        let shown = if name.contains("bytes") || name.starts_with("wasted") {
+            format!("{} ({} bytes)", human_size(value), value)
+        } else {
+            value.to_string()
+        };
+        let _ = writeln!(
+            res,
+            "<tr><td>{}</td><td>{}</td></tr>",
+            html_escape(&name),
+            shown
+        );
+    }
+    let _ = writeln!(res, "</table></details>");
+    let _ = writeln!(
+        res,
+        "<p class=\"filters\">Extension: <input id=\"ext\" oninput=\"applyFilters()\"> Directory: <input id=\"dir\" oninput=\"applyFilters()\"></p>"
+    );
+
+    for (opt, files) in groups {
+        let wasted = wasted_bytes(files);
+        let _ = writeln!(
+            res,
+            "<details class=\"group\"><summary><span class=\"wasted\">{}</span> in {} files &mdash; {}</summary>\n<ul>",
+            human_size(wasted),
+            files.len(),
+            html_escape(&opt.to_string())
+        );
+
+        for file in files.iter() {
+            let path = file.name.clone().unwrap_or_default();
+            let _ = writeln!(
+                res,
+                "<li data-ext=\"{}\" data-dir=\"{}\">{} <span class=\"meta\">{}, modified {}</span></li>",
+                html_escape(&file_extension(&path)),
+                html_escape(&file_dir(&path)),
+                html_escape(&path),
+                human_size(file.size.unwrap_or_default()),
+                format_time(file.modified).unwrap_or_default()
+            );
+        }
+
+        let _ = writeln!(res, "</ul></details>");
+    }
+
+    res + HTML_SCRIPT
+}
+
+pub fn render(
+    cfg: &ConfigFile,
+    policies: &[KeepPolicy],
+    res_vec: &[(&CheckOptions, &Vec<CheckOptions>)],
+    summary: &Summary,
+) -> String {
+    match cfg.format {
+        OutputFormat::Text => render_text(res_vec, summary),
+        OutputFormat::Json => render_json(res_vec, summary),
+        OutputFormat::Csv => render_table(cfg, policies, res_vec, summary, ',', csv_field),
+        OutputFormat::Tsv => render_table(cfg, policies, res_vec, summary, '\t', tsv_field),
+        OutputFormat::Html => render_html(res_vec, summary),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use super::*;
+    use crate::check::check_unchanged;
+
+    #[test]
+    fn json_results_round_trip() {
+        let dir = std::env::temp_dir().join(format!("fundoubler-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        // Nanoseconds must survive, or check_unchanged refuses every file
+        let mtime = UNIX_EPOCH + Duration::new(1_700_000_000, 123_456_789);
+        let mut files = Vec::new();
+        for name in ["a.txt", "b.txt"] {
+            let path = dir.join(name);
+            fs::write(&path, "same").unwrap();
+            fs::File::options()
+                .write(true)
+                .open(&path)
+                .unwrap()
+                .set_modified(mtime)
+                .unwrap();
+
+            let metadata = fs::metadata(&path).unwrap();
+            files.push(CheckOptions {
+                name: Some(path.to_string_lossy().to_string()),
+                size: Some(metadata.len()),
+                created: metadata.created().ok(),
+                modified: metadata.modified().ok(),
+                ..CheckOptions::new()
+            });
+        }
+
+        let mut key = CheckOptions::new();
+        key.size = Some(4);
+        key.digests.insert(HashAlgorithm::Md5, String::from("abc"));
+
+        let json = render_json(&[(&key, &files)], &Summary::default());
+        let results = dir.join("results.json");
+        fs::write(&results, json).unwrap();
+
+        let groups = read_results(&results).unwrap();
+        fs::remove_file(&results).unwrap();
+
+        assert_eq!(groups.len(), 1);
+        let (read_key, read_files) = &groups[0];
+        assert_eq!(read_key.size, Some(4));
+        assert_eq!(read_key.digests, key.digests);
+        assert_eq!(read_files.len(), 2);
+
+        for (read, orig) in read_files.iter().zip(files.iter()) {
+            assert_eq!(read.name, orig.name);
+            assert_eq!(read.modified, Some(mtime));
+            assert_eq!(read.created, orig.created);
+            assert_eq!(check_unchanged(read), Ok(()));
+        }
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn text_results_ask_for_json() {
+        let path = std::env::temp_dir().join(format!("fundoubler-text-{}.res", std::process::id()));
+        fs::write(&path, "MD5: abc\n    a.txt\n").unwrap();
+
+        let err = read_results(&path).unwrap_err();
+        assert!(err.contains("--format json"), "{}", err);
+
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,197 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::actions::replace_with;
+use crate::init::ConfigFile;
+use crate::journal::{read_journal, JournalEntry};
+use crate::quarantine::{move_file, read_manifest};
+
+enum Restored {
+    Done,
+    NothingToDo,
+    Impossible,
+}
+
+// Replaces link at original path with an independent copy of the data it points to
+fn unlink_copy(original: &Path, check_symlink: bool) -> io::Result<Restored> {
+    let metadata = fs::symlink_metadata(original)?;
+    if check_symlink && !metadata.file_type().is_symlink() {
+        return Ok(Restored::NothingToDo);
+    }
+
+    replace_with(original, |temp| fs::copy(original, temp).map(|_| ()))?;
+    Ok(Restored::Done)
+}
+
+fn restore_entry(entry: &JournalEntry) -> io::Result<Restored> {
+    let original = Path::new(&entry.original);
+
+    match entry.action.as_str() {
+        "move" => {
+            let moved_to = match &entry.moved_to {
+                Some(m) => Path::new(m),
+                None => return Ok(Restored::Impossible),
+            };
+            if original.exists() {
+                return Err(io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    "original path is occupied",
+                ));
+            }
+            move_file(moved_to, original)?;
+            Ok(Restored::Done)
+        }
+        "hardlink" => unlink_copy(original, false),
+        "symlink" => unlink_copy(original, true),
+        // Reflinked file is already an independent copy
+        "reflink" => Ok(Restored::NothingToDo),
+        _ => Ok(Restored::Impossible),
+    }
+}
+
+/// Reverses actions from journal or quarantine directory manifest, latest first
+pub fn restore(cfg: &ConfigFile, journal: &Path) {
+    let read = if journal.is_dir() {
+        read_manifest(journal)
+    } else {
+        read_journal(journal)
+    };
+
+    let entries = match read {
+        Ok(e) => e,
+        Err(e) => panic!("Can't read journal {}! {}", journal.display(), e),
+    };
+
+    let mut num_restored: usize = 0;
+    let mut impossible: Vec<&JournalEntry> = Vec::new();
+
+    for entry in entries.iter().rev() {
+        match restore_entry(entry) {
+            Ok(Restored::Done) => {
+                num_restored += 1;
+                info!("{} {} - restored!", entry.action, entry.original);
+                if !cfg.silent_mode {
+                    println!("{} {}...   restored!", entry.action, entry.original);
+                }
+            }
+            Ok(Restored::NothingToDo) => {
+                info!("{} {} - nothing to restore", entry.action, entry.original);
+                if !cfg.silent_mode {
+                    println!(
+                        "{} {}...   nothing to restore",
+                        entry.action, entry.original
+                    );
+                }
+            }
+            Ok(Restored::Impossible) => impossible.push(entry),
+            Err(e) => {
+                println!("Can't restore {} {} - {}", entry.action, entry.original, e);
+                warn!("Can't restore {} {} - {}", entry.action, entry.original, e);
+            }
+        }
+    }
+
+    if !cfg.silent_mode {
+        println!("Restored {} of {} actions", num_restored, entries.len());
+    }
+
+    if !impossible.is_empty() {
+        println!("Can't undo {} actions:", impossible.len());
+        for entry in impossible {
+            println!("    {} {}", entry.action, entry.original);
+            warn!("Can't undo {} {}", entry.action, entry.original);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::hash::Digests;
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "fundoubler-restore-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn entry(action: &str, original: &Path, kept: &Path) -> JournalEntry {
+        JournalEntry::new(
+            action,
+            original.to_string_lossy().to_string(),
+            Some(kept.to_string_lossy().to_string()),
+            &Digests::new(),
+        )
+    }
+
+    #[test]
+    fn restores_moved_file() {
+        let dir = test_dir("move");
+        let (original, kept, moved_to) = (dir.join("a"), dir.join("b"), dir.join("q/a"));
+        fs::write(&kept, "same").unwrap();
+        fs::create_dir_all(dir.join("q")).unwrap();
+        fs::write(&moved_to, "same").unwrap();
+
+        let mut moved = entry("move", &original, &kept);
+        moved.moved_to = Some(moved_to.to_string_lossy().to_string());
+
+        assert!(matches!(restore_entry(&moved), Ok(Restored::Done)));
+        assert_eq!(fs::read_to_string(&original).unwrap(), "same");
+        assert!(!moved_to.exists());
+
+        // Never overwrites a file which took the original path
+        fs::write(&moved_to, "same").unwrap();
+        assert!(restore_entry(&moved).is_err());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn restores_hard_link_as_independent_copy() {
+        use std::os::unix::fs::MetadataExt;
+
+        let dir = test_dir("hardlink");
+        let (original, kept) = (dir.join("a"), dir.join("b"));
+        fs::write(&kept, "same").unwrap();
+        fs::hard_link(&kept, &original).unwrap();
+
+        assert!(matches!(
+            restore_entry(&entry("hardlink", &original, &kept)),
+            Ok(Restored::Done)
+        ));
+        assert_eq!(fs::read_to_string(&original).unwrap(), "same");
+        assert_eq!(fs::metadata(&kept).unwrap().nlink(), 1);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn restores_symlink_as_regular_file() {
+        let dir = test_dir("symlink");
+        let (original, kept) = (dir.join("a"), dir.join("b"));
+        fs::write(&kept, "same").unwrap();
+        std::os::unix::fs::symlink(&kept, &original).unwrap();
+
+        let linked = entry("symlink", &original, &kept);
+        assert!(matches!(restore_entry(&linked), Ok(Restored::Done)));
+        assert!(!fs::symlink_metadata(&original)
+            .unwrap()
+            .file_type()
+            .is_symlink());
+        assert_eq!(fs::read_to_string(&original).unwrap(), "same");
+
+        assert!(matches!(restore_entry(&linked), Ok(Restored::NothingToDo)));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
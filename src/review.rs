@@ -0,0 +1,360 @@
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::fmt::Write as _;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write as _};
+#[cfg(unix)]
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::PathBuf;
+use std::process::Command;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use dialoguer::Confirm;
+
+use crate::actions::Action;
+use crate::check::{is_reference, CheckOptions};
+use crate::init::ConfigFile;
+use crate::keep::{select_kept, KeepPolicy};
+
+const REVIEW_HELP: &str = "\
+# Mark every file with one of the commands:
+#   keep - leave file as is
+#   del  - delete file (or move it if --move-to is set)
+#   link - replace file with a link to the first kept file of its group
+#          (hard link unless --symlink or --reflink is set)
+# Removed lines mean keep. Reference files are always kept.
+# Every group where something is deleted or linked needs a kept file.
+# Lines starting with # and empty lines are ignored.
+";
+
+/// What user decided to do with a file in review file
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mark {
+    Keep,
+    Delete,
+    Link,
+}
+
+impl FromStr for Mark {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "keep" | "k" => Ok(Mark::Keep),
+            "del" | "d" | "delete" => Ok(Mark::Delete),
+            "link" | "l" => Ok(Mark::Link),
+            _ => Err(format!("Unknown command {}", s)),
+        }
+    }
+}
+
+impl Mark {
+    pub fn command(&self) -> &'static str {
+        match self {
+            Mark::Keep => "keep",
+            Mark::Delete => "del",
+            Mark::Link => "link",
+        }
+    }
+
+    /// Action for marked file, configured one if it fits the mark
+    pub fn action(&self, configured: &Action) -> Option<Action> {
+        match self {
+            Mark::Keep => None,
+            Mark::Delete if configured.needs_target() => Some(Action::Delete),
+            Mark::Link if !configured.needs_target() => Some(Action::HardLink),
+            _ => Some(configured.clone()),
+        }
+    }
+}
+
+/// Initial marks of group files: kept and reference files are kept,
+/// others get the mark of configured action
+pub fn default_marks(
+    cfg: &ConfigFile,
+    policies: &[KeepPolicy],
+    action: &Action,
+    files: &[CheckOptions],
+) -> Vec<Mark> {
+    let kept = select_kept(cfg, policies, files);
+
+    files
+        .iter()
+        .map(|file| {
+            if kept == Some(file) || is_reference(cfg, file) {
+                Mark::Keep
+            } else if action.needs_target() {
+                Mark::Link
+            } else {
+                Mark::Delete
+            }
+        })
+        .collect()
+}
+
+fn write_review(
+    cfg: &ConfigFile,
+    policies: &[KeepPolicy],
+    action: &Action,
+    res_vec: &[(&CheckOptions, &Vec<CheckOptions>)],
+) -> String {
+    let mut res = String::from(REVIEW_HELP);
+
+    for (idx, (template, files)) in res_vec.iter().enumerate() {
+        let marks = default_marks(cfg, policies, action, files);
+
+        let _ = writeln!(res, "\n# Group {}: {}", idx + 1, template);
+        for (file, mark) in files.iter().zip(marks) {
+            let _ = writeln!(
+                res,
+                "{} {}",
+                mark.command(),
+                file.name.as_deref().unwrap_or_default()
+            );
+        }
+    }
+
+    res
+}
+
+fn parse_review(
+    cfg: &ConfigFile,
+    text: &str,
+    res_vec: &[(&CheckOptions, &Vec<CheckOptions>)],
+) -> Result<HashMap<String, Mark>, String> {
+    let mut res: HashMap<String, Mark> = HashMap::new();
+
+    let known: HashSet<&str> = res_vec
+        .iter()
+        .flat_map(|(_, files)| files.iter())
+        .filter_map(|f| f.name.as_deref())
+        .collect();
+
+    for (idx, line) in text.lines().enumerate() {
+        let line = line.trim_end_matches('\r');
+        if line.trim().is_empty() || line.trim_start().starts_with('#') {
+            continue;
+        }
+
+        let (command, path) = line
+            .trim_start()
+            .split_once(char::is_whitespace)
+            .ok_or_else(|| format!("line {}: no path after command", idx + 1))?;
+        let mark = Mark::from_str(command).map_err(|e| format!("line {}: {}", idx + 1, e))?;
+        let path = path.trim_start();
+
+        if !known.contains(path) {
+            return Err(format!("line {}: unknown file {}", idx + 1, path));
+        }
+
+        res.insert(path.to_string(), mark);
+    }
+
+    // Otherwise the survivor would depend on the order files are processed
+    for (idx, (_, files)) in res_vec.iter().enumerate() {
+        let marks: Vec<Mark> = files
+            .iter()
+            .map(|f| {
+                if is_reference(cfg, f) {
+                    return Mark::Keep;
+                }
+                f.name
+                    .as_deref()
+                    .and_then(|n| res.get(n))
+                    .copied()
+                    .unwrap_or(Mark::Keep)
+            })
+            .collect();
+
+        if marks.iter().any(|m| *m != Mark::Keep) && !marks.contains(&Mark::Keep) {
+            return Err(format!("group {}: no file is marked keep", idx + 1));
+        }
+    }
+
+    Ok(res)
+}
+
+// New file only readable by user, never an existing file or symlink planted in temp dir
+fn create_temp() -> io::Result<(PathBuf, File)> {
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    options.mode(0o600);
+
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.subsec_nanos())
+        .unwrap_or_default();
+
+    let mut attempt: u32 = 0;
+    loop {
+        let path = env::temp_dir().join(format!(
+            "fundoubler-review-{}-{:x}-{}.txt",
+            std::process::id(),
+            nanos,
+            attempt
+        ));
+        match options.open(&path) {
+            Ok(file) => return Ok((path, file)),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists && attempt < 100 => attempt += 1,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+// Editor command may have arguments, e.g. "code --wait"
+fn edit(text: &str) -> Result<String, String> {
+    let editor = env::var("VISUAL")
+        .or_else(|_| env::var("EDITOR"))
+        .unwrap_or_else(|_| {
+            if cfg!(windows) {
+                String::from("notepad")
+            } else {
+                String::from("vi")
+            }
+        });
+
+    let (path, mut file) =
+        create_temp().map_err(|e| format!("Can't create review file - {}", e))?;
+    let written = file.write_all(text.as_bytes());
+    drop(file);
+    if let Err(e) = written {
+        let _ = fs::remove_file(&path);
+        return Err(format!("Can't write {} - {}", path.display(), e));
+    }
+
+    let mut args = editor.split_whitespace();
+    let program = args
+        .next()
+        .ok_or_else(|| String::from("Editor command is empty"))?;
+
+    let status = Command::new(program)
+        .args(args)
+        .arg(&path)
+        .status()
+        .map_err(|e| format!("Can't run editor {} - {}", editor, e));
+
+    let edited = status.and_then(|s| {
+        if s.success() {
+            fs::read_to_string(&path).map_err(|e| format!("Can't read {} - {}", path.display(), e))
+        } else {
+            Err(format!("Editor {} exited with {}", editor, s))
+        }
+    });
+
+    let _ = fs::remove_file(&path);
+    edited
+}
+
+/// Lets user mark files in editor, returns marks by file path.
+/// Files without mark are kept.
+pub fn review(
+    cfg: &ConfigFile,
+    policies: &[KeepPolicy],
+    action: &Action,
+    res_vec: &[(&CheckOptions, &Vec<CheckOptions>)],
+) -> HashMap<String, Mark> {
+    let mut text = write_review(cfg, policies, action, res_vec);
+
+    loop {
+        let parsed = edit(&text).and_then(|edited| {
+            text = edited;
+            parse_review(cfg, &text, res_vec)
+        });
+
+        match parsed {
+            Ok(marks) => return marks,
+            Err(e) => {
+                println!("Review error: {}", e);
+                warn!("Review error: {}", e);
+
+                if !Confirm::new()
+                    .with_prompt("Edit review file again?")
+                    .default(true)
+                    .show_default(true)
+                    .interact()
+                    .unwrap()
+                {
+                    return HashMap::new();
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn group(paths: &[&str]) -> Vec<CheckOptions> {
+        paths
+            .iter()
+            .map(|p| CheckOptions {
+                name: Some(p.to_string()),
+                ..CheckOptions::new()
+            })
+            .collect()
+    }
+
+    fn parse(text: &str) -> Result<HashMap<String, Mark>, String> {
+        let key = CheckOptions::new();
+        let files = group(&["w/f1", "w/f2", "w/f3"]);
+        parse_review(&ConfigFile::default(), text, &[(&key, &files)])
+    }
+
+    #[test]
+    fn parses_marks_and_skips_comments() {
+        let marks = parse("# Group 1\nkeep w/f1\n\ndel w/f2\n  link w/f3\r\n").unwrap();
+
+        assert_eq!(marks.get("w/f1"), Some(&Mark::Keep));
+        assert_eq!(marks.get("w/f2"), Some(&Mark::Delete));
+        assert_eq!(marks.get("w/f3"), Some(&Mark::Link));
+    }
+
+    #[test]
+    fn keeps_spaces_inside_path() {
+        let key = CheckOptions::new();
+        let files = group(&["w/my file", "w/f2"]);
+        let marks = parse_review(
+            &ConfigFile::default(),
+            "keep w/my file\ndel w/f2",
+            &[(&key, &files)],
+        )
+        .unwrap();
+
+        assert_eq!(marks.get("w/my file"), Some(&Mark::Keep));
+    }
+
+    #[test]
+    fn rejects_bad_command() {
+        let err = parse("keep w/f1\nremove w/f2").unwrap_err();
+        assert!(err.starts_with("line 2:"), "{}", err);
+    }
+
+    #[test]
+    fn rejects_line_without_path() {
+        assert!(parse("keep").unwrap_err().starts_with("line 1:"));
+    }
+
+    #[test]
+    fn rejects_unknown_path() {
+        let err = parse("keep w/f1\ndel w/other").unwrap_err();
+        assert!(err.contains("unknown file w/other"), "{}", err);
+    }
+
+    #[test]
+    fn rejects_group_without_keep() {
+        let err = parse("del w/f1\ndel w/f2\ndel w/f3").unwrap_err();
+        assert!(err.starts_with("group 1:"), "{}", err);
+
+        let err = parse("link w/f1\nlink w/f2\ndel w/f3").unwrap_err();
+        assert!(err.starts_with("group 1:"), "{}", err);
+    }
+
+    #[test]
+    fn removed_lines_mean_keep() {
+        let marks = parse("del w/f1\ndel w/f2").unwrap();
+        assert_eq!(marks.get("w/f3"), None);
+    }
+}
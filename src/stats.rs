@@ -0,0 +1,170 @@
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+
+use serde_derive::Serialize;
+
+use crate::check::{is_reference, CheckOptions};
+use crate::init::ConfigFile;
+use crate::keep::{select_kept, KeepPolicy};
+use crate::report::{file_dir, file_extension, human_size};
+
+const TOP_COUNT: usize = 10;
+
+/// Counters collected while walking start paths
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct ScanStats {
+    pub files_scanned: u64,
+    pub bytes_scanned: u64,
+    /// Number of skipped files by filter name
+    pub skipped: BTreeMap<String, u64>,
+}
+
+impl ScanStats {
+    pub fn skip(&mut self, filter: &str) {
+        *self.skipped.entry(filter.to_string()).or_insert(0) += 1;
+    }
+}
+
+/// Wasted space in one extension or directory
+#[derive(Clone, Debug, Serialize)]
+pub struct WastedBy {
+    pub name: String,
+    pub bytes: u64,
+}
+
+/// End of run report
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct Summary {
+    #[serde(flatten)]
+    pub scan: ScanStats,
+    pub groups: usize,
+    pub redundant_copies: usize,
+    pub reclaimable_bytes: u64,
+    pub top_extensions: Vec<WastedBy>,
+    pub top_directories: Vec<WastedBy>,
+}
+
+fn top(wasted: HashMap<String, u64>) -> Vec<WastedBy> {
+    let mut res: Vec<WastedBy> = wasted
+        .into_iter()
+        .map(|(name, bytes)| WastedBy { name, bytes })
+        .collect();
+    res.sort_by(|w0, w1| {
+        Reverse(w0.bytes)
+            .cmp(&Reverse(w1.bytes))
+            .then_with(|| w0.name.cmp(&w1.name))
+    });
+    res.truncate(TOP_COUNT);
+    res
+}
+
+impl Summary {
+    /// Redundant copies are all files of a group except kept and reference ones
+    pub fn new(
+        cfg: &ConfigFile,
+        policies: &[KeepPolicy],
+        scan: ScanStats,
+        res_vec: &[(&CheckOptions, &Vec<CheckOptions>)],
+    ) -> Self {
+        let mut res = Self {
+            scan,
+            groups: res_vec.len(),
+            ..Default::default()
+        };
+        let mut extensions: HashMap<String, u64> = HashMap::new();
+        let mut directories: HashMap<String, u64> = HashMap::new();
+
+        for (_, files) in res_vec {
+            let kept = select_kept(cfg, policies, files);
+
+            for file in files.iter() {
+                if kept == Some(file) || is_reference(cfg, file) {
+                    continue;
+                }
+
+                let path = file.name.clone().unwrap_or_default();
+                let size = file.size.unwrap_or_default();
+
+                res.redundant_copies += 1;
+                res.reclaimable_bytes += size;
+
+                let ext = file_extension(&path);
+                let ext = if ext.is_empty() {
+                    String::from("(none)")
+                } else {
+                    format!(".{}", ext)
+                };
+                *extensions.entry(ext).or_insert(0) += size;
+                *directories.entry(file_dir(&path)).or_insert(0) += size;
+            }
+        }
+
+        res.top_extensions = top(extensions);
+        res.top_directories = top(directories);
+        res
+    }
+
+    /// Plain metric name and value pairs for tabular formats
+    pub fn rows(&self) -> Vec<(String, u64)> {
+        let mut res = vec![
+            (String::from("files scanned"), self.scan.files_scanned),
+            (String::from("bytes scanned"), self.scan.bytes_scanned),
+        ];
+        for (filter, count) in self.scan.skipped.iter() {
+            res.push((format!("skipped by {}", filter), *count));
+        }
+        res.push((String::from("duplicate groups"), self.groups as u64));
+        res.push((
+            String::from("redundant copies"),
+            self.redundant_copies as u64,
+        ));
+        res.push((String::from("reclaimable bytes"), self.reclaimable_bytes));
+        for w in self.top_extensions.iter() {
+            res.push((format!("wasted in extension {}", w.name), w.bytes));
+        }
+        for w in self.top_directories.iter() {
+            res.push((format!("wasted in directory {}", w.name), w.bytes));
+        }
+        res
+    }
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Summary:")?;
+        writeln!(
+            f,
+            "    Files scanned: {} ({})",
+            self.scan.files_scanned,
+            human_size(self.scan.bytes_scanned)
+        )?;
+        for (filter, count) in self.scan.skipped.iter() {
+            writeln!(f, "    Skipped by {}: {}", filter, count)?;
+        }
+        writeln!(f, "    Duplicate groups: {}", self.groups)?;
+        writeln!(f, "    Redundant copies: {}", self.redundant_copies)?;
+        writeln!(
+            f,
+            "    Reclaimable: {} ({} bytes)",
+            human_size(self.reclaimable_bytes),
+            self.reclaimable_bytes
+        )?;
+
+        if !self.top_extensions.is_empty() {
+            writeln!(f, "    Top extensions:")?;
+            for w in self.top_extensions.iter() {
+                writeln!(f, "        {} - {}", w.name, human_size(w.bytes))?;
+            }
+        }
+
+        if !self.top_directories.is_empty() {
+            writeln!(f, "    Top directories:")?;
+            for w in self.top_directories.iter() {
+                writeln!(f, "        {} - {}", w.name, human_size(w.bytes))?;
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,313 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, Read};
+use std::time::SystemTime;
+
+use chrono::offset::Utc;
+use chrono::DateTime;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Style, Stylize};
+use ratatui::widgets::{Block, List, ListItem, ListState, Paragraph, Row, Table, TableState, Wrap};
+use ratatui::{DefaultTerminal, Frame};
+
+use crate::actions::Action;
+use crate::check::{is_reference, CheckOptions};
+use crate::init::ConfigFile;
+use crate::keep::KeepPolicy;
+use crate::report::{human_size, wasted_bytes};
+use crate::review::{default_marks, Mark};
+
+const PREVIEW_SIZE: usize = 4096;
+
+const HELP: &str = "↑↓/jk move  Tab switch pane  s keep  d del  l link  \
+n keep newest  N keep newest everywhere  u undo  c commit  q quit";
+
+struct Review<'a> {
+    cfg: &'a ConfigFile,
+    action: &'a Action,
+    groups: &'a [(&'a CheckOptions, &'a Vec<CheckOptions>)],
+    marks: Vec<Vec<Mark>>,
+    history: Vec<Vec<Vec<Mark>>>,
+    group: usize,
+    file: usize,
+    files_focused: bool,
+    message: String,
+}
+
+fn format_time(time: Option<SystemTime>) -> String {
+    time.map(|t| {
+        DateTime::<Utc>::from(t)
+            .format("%Y-%m-%d %H:%M:%S")
+            .to_string()
+    })
+    .unwrap_or_default()
+}
+
+// Beginning of text file, binary files are not shown
+fn preview(path: &str) -> String {
+    let mut buf = Vec::new();
+    let read = File::open(path).and_then(|f| f.take(PREVIEW_SIZE as u64).read_to_end(&mut buf));
+
+    match read {
+        Ok(_) if buf.contains(&0) => String::from("(binary file)"),
+        Ok(_) => String::from_utf8_lossy(&buf).to_string(),
+        Err(e) => format!("Can't read {} - {}", path, e),
+    }
+}
+
+impl<'a> Review<'a> {
+    fn files(&self) -> &'a [CheckOptions] {
+        self.groups[self.group].1
+    }
+
+    fn move_selection(&mut self, down: bool) {
+        let (pos, len) = if self.files_focused {
+            (&mut self.file, self.groups[self.group].1.len())
+        } else {
+            (&mut self.group, self.groups.len())
+        };
+
+        if down && *pos + 1 < len {
+            *pos += 1;
+        } else if !down && *pos > 0 {
+            *pos -= 1;
+        }
+
+        if !self.files_focused {
+            self.file = 0;
+        }
+    }
+
+    fn set_mark(&mut self, mark: Mark) {
+        if !self.files_focused {
+            self.message = String::from("Select a file first (Tab)");
+            return;
+        }
+
+        if is_reference(self.cfg, &self.files()[self.file]) {
+            self.message = String::from("Reference files are always kept");
+            return;
+        }
+
+        self.history.push(self.marks.clone());
+        self.marks[self.group][self.file] = mark;
+        self.message.clear();
+    }
+
+    fn keep_newest(&mut self, all: bool) {
+        let newest = [KeepPolicy::Newest];
+        self.history.push(self.marks.clone());
+
+        for (idx, (_, files)) in self.groups.iter().enumerate() {
+            if all || idx == self.group {
+                self.marks[idx] = default_marks(self.cfg, &newest, self.action, files);
+            }
+        }
+        self.message.clear();
+    }
+
+    fn undo(&mut self) {
+        match self.history.pop() {
+            Some(marks) => {
+                self.marks = marks;
+                self.message.clear();
+            }
+            None => self.message = String::from("Nothing to undo"),
+        }
+    }
+
+    // Links need a kept file in the same group, and the survivor of a group
+    // must be chosen by user, not by the order files are processed
+    fn check_kept(&self) -> Result<(), String> {
+        for (idx, marks) in self.marks.iter().enumerate() {
+            if marks.iter().any(|m| *m != Mark::Keep) && !marks.contains(&Mark::Keep) {
+                return Err(format!("Group {} has no kept file", idx + 1));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn result(&self) -> HashMap<String, Mark> {
+        self.groups
+            .iter()
+            .zip(self.marks.iter())
+            .flat_map(|((_, files), marks)| files.iter().zip(marks.iter()))
+            .filter_map(|(file, mark)| file.name.clone().map(|n| (n, *mark)))
+            .collect()
+    }
+
+    fn draw(&self, frame: &mut Frame) {
+        let [main, status, help] = Layout::vertical([
+            Constraint::Min(3),
+            Constraint::Length(1),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+        let [groups_area, right] =
+            Layout::horizontal([Constraint::Percentage(35), Constraint::Percentage(65)])
+                .areas(main);
+        let [files_area, preview_area] =
+            Layout::vertical([Constraint::Percentage(50), Constraint::Percentage(50)]).areas(right);
+
+        let focused = Style::new().fg(Color::Yellow);
+        let highlight = Style::new().reversed();
+
+        let group_items: Vec<ListItem> = self
+            .groups
+            .iter()
+            .zip(self.marks.iter())
+            .map(|((template, files), marks)| {
+                let changed = marks.iter().filter(|m| **m != Mark::Keep).count();
+                ListItem::new(format!(
+                    "{} files, {} wasted, {} marked - {}",
+                    files.len(),
+                    human_size(wasted_bytes(files)),
+                    changed,
+                    template
+                ))
+            })
+            .collect();
+
+        let mut groups_block = Block::bordered().title(format!(" Groups ({}) ", self.groups.len()));
+        if !self.files_focused {
+            groups_block = groups_block.border_style(focused);
+        }
+        frame.render_stateful_widget(
+            List::new(group_items)
+                .block(groups_block)
+                .highlight_style(highlight),
+            groups_area,
+            &mut ListState::default().with_selected(Some(self.group)),
+        );
+
+        let rows: Vec<Row> = self
+            .files()
+            .iter()
+            .zip(self.marks[self.group].iter())
+            .map(|(file, mark)| {
+                let mark = if is_reference(self.cfg, file) {
+                    String::from("keep (ref)")
+                } else {
+                    mark.command().to_string()
+                };
+                Row::new([
+                    mark,
+                    human_size(file.size.unwrap_or_default()),
+                    format_time(file.modified),
+                    format_time(file.created),
+                    file.name.clone().unwrap_or_default(),
+                ])
+            })
+            .collect();
+
+        let mut files_block = Block::bordered().title(" Files ");
+        if self.files_focused {
+            files_block = files_block.border_style(focused);
+        }
+        frame.render_stateful_widget(
+            Table::new(
+                rows,
+                [
+                    Constraint::Length(10),
+                    Constraint::Length(10),
+                    Constraint::Length(19),
+                    Constraint::Length(19),
+                    Constraint::Min(10),
+                ],
+            )
+            .header(Row::new(["Mark", "Size", "Modified", "Created", "Path"]).bold())
+            .block(files_block)
+            .row_highlight_style(highlight),
+            files_area,
+            &mut TableState::default().with_selected(if self.files_focused {
+                Some(self.file)
+            } else {
+                None
+            }),
+        );
+
+        let file = &self.files()[self.file];
+        frame.render_widget(
+            Paragraph::new(preview(file.name.as_deref().unwrap_or_default()))
+                .block(Block::bordered().title(format!(
+                    " Preview: {} ",
+                    file.name.as_deref().unwrap_or_default()
+                )))
+                .wrap(Wrap { trim: false }),
+            preview_area,
+        );
+
+        frame.render_widget(Paragraph::new(self.message.as_str()).fg(Color::Red), status);
+        frame.render_widget(Paragraph::new(HELP).reversed(), help);
+    }
+
+    fn run(&mut self, terminal: &mut DefaultTerminal) -> io::Result<Option<HashMap<String, Mark>>> {
+        loop {
+            terminal.draw(|frame| self.draw(frame))?;
+
+            let key = match event::read()? {
+                Event::Key(key) if key.kind == KeyEventKind::Press => key,
+                _ => continue,
+            };
+
+            match key.code {
+                KeyCode::Up | KeyCode::Char('k') => self.move_selection(false),
+                KeyCode::Down | KeyCode::Char('j') => self.move_selection(true),
+                KeyCode::Tab | KeyCode::Left | KeyCode::Right | KeyCode::Enter => {
+                    self.files_focused = !self.files_focused;
+                }
+                KeyCode::Char('s') => self.set_mark(Mark::Keep),
+                KeyCode::Char('d') => self.set_mark(Mark::Delete),
+                KeyCode::Char('l') => self.set_mark(Mark::Link),
+                KeyCode::Char('n') => self.keep_newest(false),
+                KeyCode::Char('N') => self.keep_newest(true),
+                KeyCode::Char('u') => self.undo(),
+                KeyCode::Char('c') => match self.check_kept() {
+                    Ok(()) => return Ok(Some(self.result())),
+                    Err(e) => self.message = e,
+                },
+                KeyCode::Char('q') | KeyCode::Esc => return Ok(None),
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Full-screen review of sorted groups, returns marks by file path
+/// or None if user quit without commit
+pub fn review(
+    cfg: &ConfigFile,
+    policies: &[KeepPolicy],
+    action: &Action,
+    res_vec: &[(&CheckOptions, &Vec<CheckOptions>)],
+) -> Option<HashMap<String, Mark>> {
+    if res_vec.is_empty() {
+        return Some(HashMap::new());
+    }
+
+    let mut state = Review {
+        cfg,
+        action,
+        groups: res_vec,
+        marks: res_vec
+            .iter()
+            .map(|(_, files)| default_marks(cfg, policies, action, files))
+            .collect(),
+        history: Vec::new(),
+        group: 0,
+        file: 0,
+        files_focused: false,
+        message: String::new(),
+    };
+
+    let mut terminal = ratatui::init();
+    let res = state.run(&mut terminal);
+    ratatui::restore();
+
+    match res {
+        Ok(marks) => marks,
+        Err(e) => panic!("Terminal error! {}", e),
+    }
+}